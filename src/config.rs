@@ -7,12 +7,16 @@
 
 use std::env::current_dir;
 use std::path::PathBuf;
+use egui::Key;
 use crate::FreqMagnitude;
 use crate::gui::Radix;
+use crate::gui::graphicsview::DisplayScale;
+use crate::emulator::emu_debug::UartMode;
 
 /// Configuration struct. For persistent settings.
 /// This is automatically serialized and deserialized by serde.
 #[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct Config {
     // --- General
     /// Remember current working directory for file dialogs.
@@ -22,6 +26,29 @@ pub struct Config {
     pub emu_cpuspeedmul: FreqMagnitude,
     pub emu_speed: f32,
 
+    // --- Devices
+    /// Host file read by =STDIN. None leaves the device without input.
+    pub dev_stdin_path: Option<PathBuf>,
+    /// Host file appended to by =STDOUT. None leaves the device without output.
+    pub dev_stdout_path: Option<PathBuf>,
+    /// MIDI output is recorded into this .mid file. None disables recording.
+    pub dev_midi_record_path: Option<PathBuf>,
+    /// PSG output is rendered into this .wav file. None disables recording.
+    pub dev_psg_record_path: Option<PathBuf>,
+    /// Disk image file for the disk drive. None leaves the drive empty.
+    pub dev_disk_image_path: Option<PathBuf>,
+    /// Printer output is appended to this file. None only prints into the Printer panel.
+    pub dev_printer_path: Option<PathBuf>,
+    /// What the serial port is connected to.
+    pub dev_uart_mode: UartMode,
+    /// TCP address for the serial port to listen on or connect to.
+    pub dev_uart_addr: String,
+    /// Seed the random number generator from dev_rng_seed, instead of the host clock.
+    pub dev_rng_deterministic: bool,
+    pub dev_rng_seed: i32,
+    /// Display refresh rate in Hz of emulated time.
+    pub dev_display_refresh_rate: f32,
+
     // --- Memory Explorer
     pub memview_visible: bool,
     /// Memory view follows PC register while playing
@@ -35,6 +62,11 @@ pub struct Config {
 
     // --- Graphics Display
     pub display_visible: bool,
+    pub display_scale: DisplayScale,
+    /// Pixel inspector is on. Display doesn't take input while inspecting.
+    pub display_inspect: bool,
+    /// Host keys for gamepad buttons, in button bit order: Up, Down, Left, Right, A, B, Select, Start
+    pub pad_bindings: [Key; 8],
 
     // --- CPU State
     pub cpuview_visible: bool,
@@ -42,6 +74,22 @@ pub struct Config {
 
     // --- Legacy Terminal
     pub legacyterm_visible: bool,
+
+    // --- Printer
+    pub printer_visible: bool,
+
+    // --- Text Display
+    pub textview_visible: bool,
+
+    // --- Audio Mixer
+    pub mixer_visible: bool,
+    /// Channels muted in live PSG output. Bit n is channel n.
+    pub mixer_mute: i32,
+    /// Channels soloed in live PSG output. Bit n is channel n.
+    pub mixer_solo: i32,
+
+    // --- PSG Channels
+    pub psgview_visible: bool,
 }
 
 impl Default for Config {
@@ -52,6 +100,18 @@ impl Default for Config {
             emu_speed: 10.,
             emu_cpuspeedmul: FreqMagnitude::Hz,
 
+            dev_stdin_path: None,
+            dev_stdout_path: None,
+            dev_midi_record_path: None,
+            dev_psg_record_path: None,
+            dev_disk_image_path: None,
+            dev_printer_path: None,
+            dev_uart_mode: Default::default(),
+            dev_uart_addr: "127.0.0.1:9191".into(),
+            dev_rng_deterministic: false,
+            dev_rng_seed: 0,
+            dev_display_refresh_rate: 60.,
+
             memview_visible: true,
             memview_follow_pc: true,
             memview_addr_base: Default::default(),
@@ -59,11 +119,33 @@ impl Default for Config {
             memview_breakpoints_enabled: false,
 
             display_visible: false,
+            display_scale: Default::default(),
+            display_inspect: false,
+            pad_bindings: [
+                Key::ArrowUp,
+                Key::ArrowDown,
+                Key::ArrowLeft,
+                Key::ArrowRight,
+                Key::X,
+                Key::Z,
+                Key::A,
+                Key::S,
+            ],
 
             cpuview_visible: false,
             cpuview_regs_base: Default::default(),
 
             legacyterm_visible: false,
+
+            printer_visible: false,
+
+            textview_visible: false,
+
+            mixer_visible: false,
+            mixer_mute: 0,
+            mixer_solo: 0,
+
+            psgview_visible: false,
         }
     }
 }
\ No newline at end of file
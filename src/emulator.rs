@@ -35,7 +35,8 @@ use std::collections::HashSet;
 ///         Performance monitor
 ///
 ///
-use std::sync::mpsc::{Receiver, Sender};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -45,7 +46,7 @@ mod perfmon;
 #[cfg(test)]
 mod tests;
 
-use image::Rgba;
+use image::RgbaImage;
 use libttktk::b91::B91;
 use crate::emulator::cpu::GPR;
 
@@ -63,7 +64,7 @@ pub fn run(
     tx_devcrt: Sender<i32>,
     rx_devkbd: Receiver<i32>,
     tx_devkbdreq: Sender<()>,
-    tx_devdisplay: Sender<Vec<Rgba<u8>>>,
+    tx_devdisplay: Sender<(i32, RgbaImage)>,
 ) {
     let mut emu = Emu::new(tx, rx, tx_devcrt, rx_devkbd, tx_devkbdreq, tx_devdisplay);
     loop {
@@ -71,6 +72,28 @@ pub fn run(
     }
 }
 
+/// Run a program without the GUI, and record the PSG output into a WAV file. Runs for `seconds`
+/// of emulated time at `tick_rate` instructions per second, even if the program halts earlier, so
+/// notes can ring out.
+pub fn render_wav(b91: B91, path: PathBuf, seconds: f64, tick_rate: f32) {
+    let (tx, _rx_reply) = mpsc::channel();
+    let (_tx_ctrl, rx) = mpsc::channel();
+    let (tx_devcrt, _rx_devcrt) = mpsc::channel();
+    // Nobody can type, so keyboard reads fail instead of waiting forever.
+    let (_, rx_devkbd) = mpsc::channel();
+    let (tx_devkbdreq, _rx_devkbdreq) = mpsc::channel();
+    let (tx_devdisplay, _rx_devdisplay) = mpsc::channel();
+    let mut emu = Emu::new(tx, rx, tx_devcrt, rx_devkbd, tx_devkbdreq, tx_devdisplay);
+    emu.tick_rate = tick_rate;
+    emu.bus.psg.set_record_path(Some(path));
+    emu.load_b91(b91);
+    emu.start();
+    for _ in 0..(seconds * tick_rate as f64) as u64 {
+        emu.tick_ignore_breakpoints();
+    }
+    emu.stop();
+}
+
 pub struct Emu {
     bus: Bus,
     cpu: CPU,
@@ -100,7 +123,7 @@ impl Emu {
         tx_devcrt: Sender<i32>,
         rx_devkbd: Receiver<i32>,
         tx_devkbdreq: Sender<()>,
-        tx_devdisplay: Sender<Vec<Rgba<u8>>>,
+        tx_devdisplay: Sender<(i32, RgbaImage)>,
     ) -> Self {
         let mut emu = Emu {
             bus: Bus::new(),
@@ -139,6 +162,10 @@ impl Emu {
             if self.time_to_run(cyclecount) {
                 for _ in 0..cyclecount {
                     self.tick();
+                    // Stopped on a breakpoint or a watchpoint
+                    if !self.playing {
+                        break;
+                    }
                 }
                 self.slow_checks();
             } else {
@@ -166,6 +193,8 @@ impl Emu {
     pub fn manual_tick(&mut self) {
         self.tick_ignore_breakpoints();
         self.slow_checks();
+        // The next VBlank may be thousands of steps away, show what has been drawn so far.
+        self.bus.capture_frame();
     }
 
     /// Things that don't have to be done every cycle
@@ -184,24 +213,33 @@ impl Emu {
         self.t_last_update = Some(now);
         if self.playing {
             self.tick_timer += self.t_delta;
+            self.bus.pic.update_timer(self.t_delta);
         }
-        // self.bus.pic.update_timer(self.t_delta);
     }
 
     /// Fast update: every cpu tick
     fn dev_update(&mut self) {
+        self.bus.advance_time(1. / self.tick_rate as f64);
         // Interrupts
-        // if self.bus.pic.is_firing() {
-        //     self.cpu.exception_irq(&mut self.bus);
-        // }
+        self.bus.update_interrupts();
+        if let Some(vector) = self.bus.pic.firing_vector() {
+            self.cpu.exception_irq(&mut self.bus, vector);
+        }
     }
 
     /// Slow update: every frame or so
     fn dev_update_slow(&mut self) {
         self.bus.display.send();
-        // if self.bus.display.interrupt {
-        //     self.bus.pic.flag |= 0b_0100;
-        // }
+        let printed = self.bus.printer.take_output();
+        if !printed.is_empty() {
+            let _ = self.tx.send(ReplyMSG::PrinterOutput(printed));
+        }
+        if let Some(screen) = self.bus.text.take_screen() {
+            let _ = self.tx.send(ReplyMSG::TextScreen(screen));
+        }
+        if let Some(state) = self.bus.psg.take_state() {
+            let _ = self.tx.send(ReplyMSG::PsgState(state));
+        }
     }
 
     fn start(&mut self) {
@@ -216,8 +254,9 @@ impl Emu {
         self.t_last_update = None;
         self.running = false;
         self.playing = false;
-        // Send framebuffer to avoid incomplete picture
-        self.bus.display.send();
+        // Send display output to avoid incomplete picture
+        self.bus.capture_frame();
+        self.dev_update_slow();
         self.bus.turn_off();
     }
 
@@ -229,6 +268,9 @@ impl Emu {
             // Perform one tick ignoring breakpoints, in case we're stopped on one.
             self.tick_ignore_breakpoints();
             thread::sleep(Duration::from_secs_f32(1. / self.tick_rate));
+        } else {
+            // Show what has been drawn since the last VBlank.
+            self.bus.capture_frame();
         }
     }
 
@@ -272,6 +314,8 @@ impl Emu {
         }
 
         self.loaded_prog = Some(b91);
+        // Loading isn't the program writing.
+        self.bus.watch_hit = false;
     }
     fn reset(&mut self) {
         self.stop();
@@ -303,6 +347,9 @@ impl Emu {
             }
         }
         self.cpu.tick(&mut self.bus);
+        if std::mem::take(&mut self.bus.watch_hit) {
+            self.playpause(false);
+        }
     }
 
     /// Advance the emulator by one instruction. Ignore breakpoints,
@@ -312,5 +359,6 @@ impl Emu {
             return;
         }
         self.cpu.tick(&mut self.bus);
+        self.bus.watch_hit = false;
     }
 }
@@ -126,32 +126,14 @@ impl CPU {
         self.enter_interrupt_handler(bus, 3);
     }
 
-    /// Interrupt trap
-    #[allow(dead_code)] // Interrupts are are TODO
-    pub(crate) fn exception_irq(&mut self, bus: &mut Bus) {
+    /// Interrupt trap. `handler_idx` is the IVT entry given by the PIC.
+    pub(crate) fn exception_irq(&mut self, bus: &mut Bus, handler_idx: i32) {
         // Interrupts disabled.
         if self.cu_sr & SR_D != 0 {
             return;
         }
         self.halt = false;
-
-
-        /*
-        
-        // idk how this is supposed to work.
-        // I assume it goes to tr.
-        match self.cu_tr {
-            5 => self.enter_interrupt_handler(bus, 5),
-            6 => self.enter_interrupt_handler(bus, 6),
-            7 => self.enter_interrupt_handler(bus, 7),
-            8 => self.enter_interrupt_handler(bus, 8),
-            9 => self.enter_interrupt_handler(bus, 9),
-            10 => self.enter_interrupt_handler(bus, 10),
-            _ => panic!("interrupt id wtf {}", self.cu_tr),
-        }
-        */
-        // Temporary solution: don't check tr at all. We only have a timer interrupt anyway.
-        self.enter_interrupt_handler(bus, 6);
+        self.enter_interrupt_handler(bus, handler_idx);
     }
 
     /// Exception handler for service calls
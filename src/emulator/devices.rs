@@ -5,20 +5,39 @@
 //!
 //! If you're writing a new device, it must implement the Device trait, and at least one of the IO traits.
 
+use std::collections::HashSet;
+use self::dev_blitter::{Blit, BlitOp};
+use self::dev_pic::{
+    MASK_BLITTER, MASK_DISK, MASK_KBD, MASK_MOUSE, MASK_PRINTER, MASK_UART, MASK_VBLANK,
+};
+use self::dev_psg::REG_PCM_TRIGGER;
 use self::{
-    dev_crt::DevCRT, dev_display_classic::DevDisplayClassic, dev_kbd::DevKBD, // dev_pic::DevPIC,
-    dev_psg::DevPSG, dev_ram::DevRAM, dev_rtc::DevRTC,
+    dev_blitter::DevBlitter, dev_crt::DevCRT, dev_disk::DevDisk,
+    dev_display_classic::DevDisplayClassic, dev_kbd::DevKBD, dev_midi::DevMIDI,
+    dev_mouse::DevMouse, dev_pad::DevPad, dev_pic::DevPIC, dev_ppu::DevPPU,
+    dev_printer::DevPrinter, dev_psg::DevPSG, dev_ram::DevRAM, dev_rawkbd::DevRawKBD,
+    dev_rng::DevRNG, dev_rtc::DevRTC, dev_stdio::DevStdIO, dev_text::DevText, dev_uart::DevUART,
 };
 
+mod dev_blitter;
 mod dev_crt;
+mod dev_disk;
 mod dev_display_classic;
 mod dev_kbd;
 mod dev_midi;
+mod dev_mouse;
 mod dev_pad;
-// mod dev_pic;
+mod dev_pic;
+mod dev_ppu;
+mod dev_printer;
 mod dev_psg;
 mod dev_ram;
+mod dev_rawkbd;
+mod dev_rng;
 mod dev_rtc;
+mod dev_stdio;
+mod dev_text;
+mod dev_uart;
 
 /// All devices should implement this trait.
 pub(crate) trait Device {
@@ -51,25 +70,58 @@ pub(crate) trait PMIO: Device {
 /// The Bus struct is the parent of all devices, and maps IO calls to them.
 /// Essentially it determines the hardware configuration of the machine.
 pub struct Bus {
+    pub(crate) blitter: DevBlitter,
     pub(crate) crt: DevCRT,
+    pub(crate) disk: DevDisk,
     pub(crate) display: DevDisplayClassic,
     pub(crate) kbd: DevKBD,
-    // pub(crate) pic: DevPIC,
+    pub(crate) midi: DevMIDI,
+    pub(crate) mouse: DevMouse,
+    pub(crate) pad: DevPad,
+    pub(crate) pic: DevPIC,
+    pub(crate) ppu: DevPPU,
+    pub(crate) printer: DevPrinter,
     pub(crate) psg: DevPSG,
     pub(crate) ram: DevRAM,
+    pub(crate) rawkbd: DevRawKBD,
+    pub(crate) rng: DevRNG,
     pub(crate) rtc: DevRTC,
+    pub(crate) stdio: DevStdIO,
+    pub(crate) text: DevText,
+    pub(crate) uart: DevUART,
+    /// Emulated time in seconds since the machine was turned on.
+    time: f64,
+    /// Addresses that pause the emulator when written to.
+    pub(crate) watchpoints: HashSet<u32>,
+    /// Set when a watched address is written to.
+    pub(crate) watch_hit: bool,
 }
 
 impl Bus {
     pub fn new() -> Self {
         Bus {
+            blitter: DevBlitter::default(),
             crt: DevCRT::default(),
+            disk: DevDisk::default(),
             display: DevDisplayClassic::default(),
             kbd: DevKBD::default(),
-            // pic: DevPIC::default(),
+            midi: DevMIDI::default(),
+            mouse: DevMouse::default(),
+            pad: DevPad::default(),
+            pic: DevPIC::default(),
+            ppu: DevPPU::default(),
+            printer: DevPrinter::default(),
             psg: DevPSG::default(),
             ram: DevRAM::default(),
+            rawkbd: DevRawKBD::default(),
+            rng: DevRNG::default(),
             rtc: DevRTC::default(),
+            stdio: DevStdIO::default(),
+            text: DevText::default(),
+            uart: DevUART::default(),
+            time: 0.,
+            watchpoints: HashSet::new(),
+            watch_hit: false,
         }
     }
     /// MMIO access
@@ -79,18 +131,38 @@ impl Bus {
             0x0000..=0x1fff => self.ram.read(addr),
             0x2000..=0x6aff => self.display.read(addr - 0x2000),
             0x6b00..=0x6bff => self.psg.read(addr - 0x6b00),
+            0x6c00..=0x6cff => self.display.read(addr - 0x2000),
+            0x7000..=0x7fff => self.ppu.read(addr - 0x7000),
+            0x8000..=0x8fff => self.text.read(addr - 0x8000),
             _ => {
                 println!("mem read fault: 0x{:x}", addr);
                 Err(())
             }
         }
     }
+    /// Like `read()`, but the framebuffer is read from the page that is on screen.
+    pub(crate) fn read_shown(&mut self, addr: u32) -> Result<i32, ()> {
+        match addr {
+            0x2000..=0x6aff => self.display.read_shown(addr as usize - 0x2000),
+            _ => self.read(addr),
+        }
+    }
     pub(crate) fn write(&mut self, addr: u32, value: i32) -> Result<(), ()> {
+        if self.watchpoints.contains(&addr) {
+            self.watch_hit = true;
+        }
         let addr = addr as usize;
         match addr {
             0x0000..=0x1fff => self.ram.write(addr, value),
             0x2000..=0x6aff => self.display.write(addr - 0x2000, value),
+            a if a == 0x6b00 + REG_PCM_TRIGGER && value != 0 => {
+                self.load_pcm()?;
+                self.psg.write(REG_PCM_TRIGGER, value)
+            }
             0x6b00..=0x6bff => self.psg.write(addr - 0x6b00, value),
+            0x6c00..=0x6cff => self.display.write(addr - 0x2000, value),
+            0x7000..=0x7fff => self.ppu.write(addr - 0x7000, value),
+            0x8000..=0x8fff => self.text.write(addr - 0x8000, value),
             _ => {
                 println!("mem write fault: 0x{:x}", addr);
                 Err(())
@@ -104,11 +176,46 @@ impl Bus {
             0 => self.crt.read_port(0),
             1 => self.kbd.read_port(0),
             2 => self.rtc.read_port(0),
-            //6 => stdin
-            //7 => stdout
-            //0x20 => self.pic.read_port(0),
-            //0x21 => self.pic.read_port(1),
-            //0x22 => self.pic.read_port(2),
+            3 => self.rng.read_port(0),
+            4 => self.rng.read_port(1),
+            6 => self.stdio.read_port(0),
+            7 => self.stdio.read_port(1),
+            0x20 => self.pic.read_port(0),
+            0x21 => self.pic.read_port(1),
+            0x22 => self.pic.read_port(2),
+            0x30 => self.midi.read_port(0),
+            0x40 => self.pad.read_port(0),
+            0x41 => self.pad.read_port(1),
+            0x50 => self.rawkbd.read_port(0),
+            0x51 => self.rawkbd.read_port(1),
+            0x52 => self.rawkbd.read_port(2),
+            0x58 => self.mouse.read_port(0),
+            0x59 => self.mouse.read_port(1),
+            0x5a => self.mouse.read_port(2),
+            0x5b => self.mouse.read_port(3),
+            0x60 => self.disk.read_port(0),
+            0x61 => self.disk.read_port(1),
+            0x62 => self.disk.read_port(2),
+            0x63 => self.disk.read_port(3),
+            0x64 => self.disk.read_port(4),
+            0x68 => self.printer.read_port(0),
+            0x69 => self.printer.read_port(1),
+            0x6a => self.printer.read_port(2),
+            0x70 => self.uart.read_port(0),
+            0x71 => self.uart.read_port(1),
+            0x80 => self.display.read_port(0),
+            0x81 => self.display.read_port(1),
+            0x82 => self.display.read_port(2),
+            0x83 => self.display.read_port(3),
+            0x84 => self.display.read_port(4),
+            0x90 => self.blitter.read_port(0),
+            0x91 => self.blitter.read_port(1),
+            0x92 => self.blitter.read_port(2),
+            0x93 => self.blitter.read_port(3),
+            0x94 => self.blitter.read_port(4),
+            0x95 => self.blitter.read_port(5),
+            0x96 => self.blitter.read_port(6),
+            0x97 => self.blitter.read_port(7),
             _ => {
                 println!("port read fault: {:x}", port);
                 Err(())
@@ -121,11 +228,51 @@ impl Bus {
             0 => self.crt.write_port(0, value),
             1 => self.kbd.write_port(0, value),
             2 => self.rtc.write_port(0, value),
-            //6 => stdin
-            //7 => stdout
-            //0x20 => self.pic.write_port(0, value),
-            //0x21 => self.pic.write_port(1, value),
-            //0x22 => self.pic.write_port(2, value),
+            3 => self.rng.write_port(0, value),
+            4 => self.rng.write_port(1, value),
+            6 => self.stdio.write_port(0, value),
+            7 => self.stdio.write_port(1, value),
+            0x20 => self.pic.write_port(0, value),
+            0x21 => self.pic.write_port(1, value),
+            0x22 => self.pic.write_port(2, value),
+            0x23 => self.pic.write_port(3, value),
+            0x30 => self.midi.write_port(0, value),
+            0x40 => self.pad.write_port(0, value),
+            0x41 => self.pad.write_port(1, value),
+            0x50 => self.rawkbd.write_port(0, value),
+            0x51 => self.rawkbd.write_port(1, value),
+            0x52 => self.rawkbd.write_port(2, value),
+            0x58 => self.mouse.write_port(0, value),
+            0x59 => self.mouse.write_port(1, value),
+            0x5a => self.mouse.write_port(2, value),
+            0x5b => self.mouse.write_port(3, value),
+            0x60 => self.disk.write_port(0, value),
+            0x61 => self.disk.write_port(1, value),
+            0x62 => self.disk.write_port(2, value),
+            0x63 => self.disk.write_port(3, value),
+            0x64 => self.disk.write_port(4, value),
+            0x68 => self.printer.write_port(0, value),
+            0x69 => self.printer.write_port(1, value),
+            0x6a => self.printer.write_port(2, value),
+            0x70 => self.uart.write_port(0, value),
+            0x71 => self.uart.write_port(1, value),
+            0x80 => self.display.write_port(0, value),
+            0x81 => self.display.write_port(1, value),
+            0x82 => self.display.write_port(2, value),
+            0x83 => self.display.write_port(3, value),
+            0x84 => self.display.write_port(4, value),
+            0x90 => {
+                self.blitter.write_port(0, value)?;
+                self.blit();
+                Ok(())
+            }
+            0x91 => self.blitter.write_port(1, value),
+            0x92 => self.blitter.write_port(2, value),
+            0x93 => self.blitter.write_port(3, value),
+            0x94 => self.blitter.write_port(4, value),
+            0x95 => self.blitter.write_port(5, value),
+            0x96 => self.blitter.write_port(6, value),
+            0x97 => self.blitter.write_port(7, value),
             _ => {
                 println!("port write fault: {:x}", port);
                 Err(())
@@ -135,44 +282,181 @@ impl Bus {
 
     /// Clear all state
     pub(crate) fn reset(&mut self) {
+        self.blitter.reset();
         self.crt.reset();
+        self.disk.reset();
         self.display.reset();
         self.kbd.reset();
-        //self.pic.reset();
+        self.midi.reset();
+        self.mouse.reset();
+        self.pad.reset();
+        self.pic.reset();
+        self.ppu.reset();
+        self.printer.reset();
         self.psg.reset();
         self.ram.reset();
+        self.rawkbd.reset();
+        self.rng.reset();
         self.rtc.reset();
+        self.stdio.reset();
+        self.text.reset();
+        self.uart.reset();
     }
 
     /// Turn the device on. May affect state, not suitable for "pausing" the device.
     pub(crate) fn turn_on(&mut self) {
+        self.time = 0.;
+        self.blitter.on();
+        self.disk.on();
+        self.midi.set_time(self.time);
         self.crt.on();
         self.display.on();
         self.kbd.on();
-        //self.pic.on();
+        self.midi.on();
+        self.mouse.on();
+        self.pad.on();
+        self.pic.on();
+        self.ppu.on();
+        self.printer.on();
         self.psg.on();
         self.ram.on();
+        self.rawkbd.on();
+        self.rng.on();
         self.rtc.on();
+        self.stdio.on();
+        self.text.on();
+        self.uart.on();
     }
 
     /// Turn the device off. May affect state, not suitable for "pausing" the device.
     pub(crate) fn turn_off(&mut self) {
+        self.blitter.off();
         self.crt.off();
+        self.disk.off();
         self.display.off();
         self.kbd.off();
-        //self.pic.off();
+        self.midi.off();
+        self.mouse.off();
+        self.pad.off();
+        self.pic.off();
+        self.ppu.off();
+        self.printer.off();
         self.psg.off();
         self.ram.off();
+        self.rawkbd.off();
+        self.rng.off();
         self.rtc.off();
+        self.stdio.off();
+        self.text.off();
+        self.uart.off();
+    }
+
+    /// Capture the display image, with the video processor's layers on top. Replaces the previous
+    /// frame if it hasn't been sent yet, so the newest one is always shown.
+    pub(crate) fn capture_frame(&mut self) {
+        let mut frame = self.display.frame();
+        self.ppu.render(&mut frame);
+        self.display.finish_frame(frame);
+    }
+
+    /// Run a command the blitter has just started.
+    fn blit(&mut self) {
+        let Some(blit) = self.blitter.take_blit() else {
+            return;
+        };
+        let result = self.run_blit(&blit);
+        self.blitter.finish(result.is_ok());
+    }
+
+    /// Copy the PCM channel's samples from memory.
+    fn load_pcm(&mut self) -> Result<(), ()> {
+        let (start, words) = self.psg.pcm_region()?;
+        let words = (start..start.checked_add(words).ok_or(())?)
+            .map(|addr| self.read(addr))
+            .collect::<Result<Vec<_>, ()>>()?;
+        self.psg.load_pcm(&words);
+        Ok(())
+    }
+
+    fn run_blit(&mut self, blit: &Blit) -> Result<(), ()> {
+        let addresses: Vec<_> = blit.addresses().collect();
+        // Read the whole source first, so overlapping copies work.
+        let values = match blit.op {
+            BlitOp::Fill => vec![blit.color; addresses.len()],
+            BlitOp::Copy | BlitOp::MaskedCopy => addresses
+                .iter()
+                .map(|(src, _)| self.read(src.ok_or(())?))
+                .collect::<Result<Vec<_>, ()>>()?,
+        };
+        for ((_, dst), value) in addresses.into_iter().zip(values) {
+            if blit.op == BlitOp::MaskedCopy && value == blit.color {
+                continue;
+            }
+            self.write(dst.ok_or(())?, value)?;
+        }
+        Ok(())
+    }
+
+    /// Advance emulated time. Devices that care about time get updated here.
+    pub(crate) fn advance_time(&mut self, delta_t: f64) {
+        self.time += delta_t;
+        self.blitter.update(delta_t);
+        self.disk.update(delta_t);
+        self.display.update(delta_t);
+        if std::mem::take(&mut self.display.frame_done) {
+            self.capture_frame();
+        }
+        self.midi.set_time(self.time);
+        self.psg.update(delta_t);
+        self.printer.update(delta_t);
+        self.text.update(delta_t);
+        self.uart.update(delta_t);
+    }
+
+    /// Pass interrupt requests from devices to the PIC.
+    pub(crate) fn update_interrupts(&mut self) {
+        if std::mem::take(&mut self.rawkbd.interrupt) {
+            self.pic.raise(MASK_KBD);
+        }
+        if std::mem::take(&mut self.mouse.interrupt) {
+            self.pic.raise(MASK_MOUSE);
+        }
+        if std::mem::take(&mut self.display.interrupt) {
+            self.pic.raise(MASK_VBLANK);
+        }
+        if std::mem::take(&mut self.disk.interrupt) {
+            self.pic.raise(MASK_DISK);
+        }
+        if std::mem::take(&mut self.printer.interrupt) {
+            self.pic.raise(MASK_PRINTER);
+        }
+        if std::mem::take(&mut self.uart.interrupt) {
+            self.pic.raise(MASK_UART);
+        }
+        if std::mem::take(&mut self.blitter.interrupt) {
+            self.pic.raise(MASK_BLITTER);
+        }
     }
 
     pub(crate) fn set_pause(&mut self, paused: bool){
+        self.blitter.set_pause(paused);
         self.crt.set_pause(paused);
+        self.disk.set_pause(paused);
         self.display.set_pause(paused);
         self.kbd.set_pause(paused);
-        //self.pic.set_pause(paused);
+        self.midi.set_pause(paused);
+        self.mouse.set_pause(paused);
+        self.pad.set_pause(paused);
+        self.pic.set_pause(paused);
+        self.ppu.set_pause(paused);
+        self.printer.set_pause(paused);
         self.psg.set_pause(paused);
         self.ram.set_pause(paused);
+        self.rawkbd.set_pause(paused);
+        self.rng.set_pause(paused);
         self.rtc.set_pause(paused);
+        self.stdio.set_pause(paused);
+        self.text.set_pause(paused);
+        self.uart.set_pause(paused);
     }
 }
@@ -0,0 +1,273 @@
+//!
+//! Blitter
+//!
+//! Fills and copies rectangles of memory in one command, so drawing a filled rectangle or a
+//! sprite doesn't take thousands of `STORE`s. It works on the whole memory space, so both RAM and
+//! display memory can be sources and destinations.
+//!
+//! A rectangle is `width` words wide and `height` rows high. Its first word is at the given
+//! address, and each row starts `stride` words after the previous one. For the 160x120 display,
+//! a rectangle at `(x, y)` starts at `0x2000 + y * 160 + x` and its stride is 160.
+//!
+//! Ports:
+//!  - Port 0: Command / Status (global port 0x90)
+//!  - Port 1: Source address (global port 0x91)
+//!  - Port 2: Destination address (global port 0x92)
+//!  - Port 3: Width (global port 0x93)
+//!  - Port 4: Height (global port 0x94)
+//!  - Port 5: Source stride (global port 0x95)
+//!  - Port 6: Destination stride (global port 0x96)
+//!  - Port 7: Color / Transparent key (global port 0x97)
+//!
+//! Status bits:
+//! | Bit | Meaning                                                             |
+//! | --- | ------------------------------------------------------------------- |
+//! | 0   | Busy: a command is running                                          |
+//! | 1   | Error: last command hit an address outside memory, and was stopped  |
+//!
+//! Commands:
+//! | Value | Command                                                                    |
+//! | ----- | -------------------------------------------------------------------------- |
+//! | 1     | Fill: set destination rectangle to color                                   |
+//! | 2     | Copy: copy source rectangle to destination                                 |
+//! | 3     | Masked copy: like copy, but words equal to the transparent key are skipped |
+//!
+//! Reading ports 1-7 returns their value. Writing port 0 starts a command.
+//!
+//! Memory is updated as soon as the command starts, and overlapping copies work as if the source
+//! was read in full first. The blitter then stays busy for the time it would take to move the
+//! words, after which the busy bit clears and the blitter interrupt (PIC bit 8, IVT entry 4) is
+//! raised, if it's enabled in the PIC.
+//!
+//! Starting a command while busy, an unknown command, a negative size, or a rectangle larger than
+//! 65536 words are errors. A rectangle with zero width or height does nothing.
+//!
+use super::{Device, PMIO};
+
+/// How long moving one word takes, in seconds of emulated time.
+const WORD_TIME: f64 = 0.000_000_1;
+/// Largest rectangle a command can cover, in words.
+const MAX_WORDS: i64 = 0x10000;
+
+const CMD_FILL: i32 = 1;
+const CMD_COPY: i32 = 2;
+const CMD_MASKED_COPY: i32 = 3;
+
+const STATUS_BUSY: i32 = 0b_01;
+const STATUS_ERROR: i32 = 0b_10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BlitOp {
+    Fill,
+    Copy,
+    MaskedCopy,
+}
+
+/// A command that Bus runs on the memory space.
+#[derive(Clone, Debug)]
+pub(crate) struct Blit {
+    pub(crate) op: BlitOp,
+    pub(crate) src: i32,
+    pub(crate) dst: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) src_stride: i32,
+    pub(crate) dst_stride: i32,
+    pub(crate) color: i32,
+}
+
+impl Blit {
+    /// Source and destination address of every word, row by row. None if it's outside the
+    /// address space.
+    pub(crate) fn addresses(&self) -> impl Iterator<Item = (Option<u32>, Option<u32>)> + '_ {
+        (0..self.height as i64).flat_map(move |y| {
+            (0..self.width as i64).map(move |x| {
+                let src = self.src as i64 + y * self.src_stride as i64 + x;
+                let dst = self.dst as i64 + y * self.dst_stride as i64 + x;
+                (u32::try_from(src).ok(), u32::try_from(dst).ok())
+            })
+        })
+    }
+}
+
+/// Blitter
+#[derive(Default)]
+pub(crate) struct DevBlitter {
+    /// Source, destination, width, height, source stride, destination stride, color
+    regs: [i32; 7],
+    /// Command that has been started, but not yet run by Bus.
+    blit: Option<Blit>,
+    /// Emulated time left until the running command finishes.
+    busy_time: Option<f64>,
+    error: bool,
+    /// Set when a command finishes. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl DevBlitter {
+    /// Advance emulated time. Finishes the running command when its time is up.
+    pub fn update(&mut self, delta_t: f64) {
+        let Some(time) = self.busy_time else {
+            return;
+        };
+        let time = time - delta_t;
+        if time > 0. {
+            self.busy_time = Some(time);
+            return;
+        }
+        self.busy_time = None;
+        self.interrupt = true;
+    }
+
+    /// Take the command that was just started. Bus runs it, and reports back with `finish()`.
+    pub(crate) fn take_blit(&mut self) -> Option<Blit> {
+        self.blit.take()
+    }
+
+    /// Bus has run the command.
+    pub(crate) fn finish(&mut self, ok: bool) {
+        self.error = !ok;
+    }
+
+    fn command(&mut self, cmd: i32) -> Result<(), ()> {
+        if self.busy_time.is_some() {
+            return Err(());
+        }
+        let op = match cmd {
+            CMD_FILL => BlitOp::Fill,
+            CMD_COPY => BlitOp::Copy,
+            CMD_MASKED_COPY => BlitOp::MaskedCopy,
+            _ => return Err(()),
+        };
+        let [src, dst, width, height, src_stride, dst_stride, color] = self.regs;
+        if width < 0 || height < 0 || width as i64 * height as i64 > MAX_WORDS {
+            return Err(());
+        }
+        // Nothing to do, and walking the rows of an empty rectangle could take ages.
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        self.blit = Some(Blit { op, src, dst, width, height, src_stride, dst_stride, color });
+        self.busy_time = Some((width * height) as f64 * WORD_TIME);
+        Ok(())
+    }
+
+    fn status(&self) -> i32 {
+        let mut status = 0;
+        if self.busy_time.is_some() {
+            status |= STATUS_BUSY;
+        }
+        if self.error {
+            status |= STATUS_ERROR;
+        }
+        status
+    }
+}
+
+impl Device for DevBlitter {
+    fn reset(&mut self) {
+        *self = DevBlitter::default();
+    }
+    fn on(&mut self) {}
+    fn off(&mut self) {
+        *self = DevBlitter::default();
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevBlitter {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.status()),
+            1..=7 => Ok(self.regs[port as usize - 1]),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        match port {
+            0 => self.command(value),
+            1..=7 => {
+                self.regs[port as usize - 1] = value;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_blitter() -> Result<(), ()> {
+        let mut blitter = DevBlitter::default();
+
+        // Test wrong usage
+        assert!(blitter.read_port(8).is_err());
+        assert!(blitter.write_port(8, 0).is_err());
+        assert!(blitter.write_port(0, 4).is_err());
+        blitter.write_port(3, -1)?;
+        assert!(blitter.write_port(0, CMD_FILL).is_err());
+        blitter.write_port(3, 1000)?;
+        blitter.write_port(4, 1000)?;
+        assert!(blitter.write_port(0, CMD_FILL).is_err());
+        assert!(blitter.take_blit().is_none());
+
+        // Empty rectangles do nothing, however tall or wide.
+        blitter.write_port(3, 0)?;
+        blitter.write_port(4, i32::MAX)?;
+        blitter.write_port(0, CMD_FILL)?;
+        blitter.write_port(3, i32::MAX)?;
+        blitter.write_port(4, 0)?;
+        blitter.write_port(0, CMD_COPY)?;
+        assert_eq!(blitter.read_port(0)?, 0);
+        assert!(blitter.take_blit().is_none());
+
+        // 3x2 masked copy from 10 to 100, into a 160 word wide screen.
+        blitter.write_port(1, 10)?;
+        blitter.write_port(2, 100)?;
+        blitter.write_port(3, 3)?;
+        blitter.write_port(4, 2)?;
+        blitter.write_port(5, 3)?;
+        blitter.write_port(6, 160)?;
+        blitter.write_port(7, 0x0f0f)?;
+        assert_eq!(blitter.read_port(6)?, 160);
+        blitter.write_port(0, CMD_MASKED_COPY)?;
+        assert_eq!(blitter.read_port(0)?, STATUS_BUSY);
+        assert!(blitter.write_port(0, CMD_FILL).is_err());
+
+        let blit = blitter.take_blit().unwrap();
+        assert!(blitter.take_blit().is_none());
+        assert_eq!(blit.op, BlitOp::MaskedCopy);
+        assert_eq!(blit.color, 0x0f0f);
+        let addresses: Vec<_> = blit.addresses().collect();
+        assert_eq!(addresses.len(), 6);
+        assert_eq!(addresses[2], (Some(12), Some(102)));
+        assert_eq!(addresses[3], (Some(13), Some(260)));
+        blitter.finish(true);
+
+        // Busy until the words would have been moved.
+        blitter.update(WORD_TIME * 5.);
+        assert_eq!(blitter.read_port(0)?, STATUS_BUSY);
+        assert!(!blitter.interrupt);
+        blitter.update(WORD_TIME * 1.5);
+        assert_eq!(blitter.read_port(0)?, 0);
+        assert!(blitter.interrupt);
+
+        // Negative addresses are outside memory.
+        blitter.write_port(2, -1)?;
+        blitter.write_port(0, CMD_FILL)?;
+        let blit = blitter.take_blit().unwrap();
+        assert_eq!(blit.addresses().next(), Some((Some(10), None)));
+        blitter.finish(false);
+        assert_eq!(blitter.read_port(0)?, STATUS_BUSY | STATUS_ERROR);
+
+        // Off resets everything.
+        blitter.off();
+        assert_eq!(blitter.read_port(0)?, 0);
+        assert_eq!(blitter.read_port(1)?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//!
+//! Disk drive
+//!
+//! Block storage device backed by a host disk image file. The disk is divided into sectors of 128
+//! words. Each word is stored in the image as a 32-bit little-endian integer, so a sector is 512
+//! bytes, and the number of sectors is the image size divided by 512.
+//!
+//! The drive has a one sector buffer. A read command copies a sector from the disk into the
+//! buffer, and a write command copies the buffer onto the disk. The program accesses the buffer
+//! one word at a time through the data port.
+//!
+//! Ports:
+//!  - Port 0: Command / Status (global port 0x60)
+//!  - Port 1: Sector (global port 0x61)
+//!  - Port 2: Data (global port 0x62)
+//!  - Port 3: Buffer position (global port 0x63)
+//!  - Port 4: Sector count (global port 0x64)
+//!
+//! Status bits:
+//! | Bit | Meaning                                                      |
+//! | --- | ------------------------------------------------------------ |
+//! | 0   | Busy: a command is running                                   |
+//! | 1   | Error: last command failed, e.g. sector was out of range     |
+//! | 2   | No disk: no image file is set, or it couldn't be opened      |
+//!
+//! Commands:
+//! | Value | Command                                  |
+//! | ----- | ---------------------------------------- |
+//! | 1     | Read selected sector into the buffer     |
+//! | 2     | Write the buffer into selected sector    |
+//!
+//! Read Behaviour:
+//! | Port | Effect                                                             |
+//! | ---- | ------------------------------------------------------------------ |
+//! | 0    | Returns status                                                     |
+//! | 1    | Returns selected sector                                            |
+//! | 2    | Returns word from buffer position, and advances the position      |
+//! | 3    | Returns buffer position                                            |
+//! | 4    | Returns number of sectors on the disk                              |
+//!
+//! Write Behaviour:
+//! | Port | Effect                                                             |
+//! | ---- | ------------------------------------------------------------------ |
+//! | 0    | Start a command. Buffer position is reset to 0.                    |
+//! | 1    | Select sector                                                      |
+//! | 2    | Write word into buffer position, and advance the position         |
+//! | 3    | Set buffer position (0-127)                                        |
+//!
+//! Buffer position wraps around after the last word.
+//!
+//! A command takes some emulated time to finish. When it's done, the busy bit clears and the disk
+//! interrupt (IVT entry 9) is raised, if it's enabled in the PIC.
+//! Starting a command or accessing the buffer while the drive is busy is an error.
+//!
+//! Writes go straight into the image file.
+//!
+use super::{Device, PMIO};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Sector size in words
+const SECTOR_WORDS: usize = 128;
+/// Sector size in bytes
+const SECTOR_BYTES: usize = SECTOR_WORDS * 4;
+/// How long a command takes, in seconds of emulated time.
+const COMMAND_TIME: f64 = 0.002;
+
+const CMD_READ: i32 = 1;
+const CMD_WRITE: i32 = 2;
+
+const STATUS_BUSY: i32 = 0b_001;
+const STATUS_ERROR: i32 = 0b_010;
+const STATUS_NO_DISK: i32 = 0b_100;
+
+/// Disk drive
+pub(crate) struct DevDisk {
+    image_path: Option<PathBuf>,
+    /// Image file is kept open while the machine is on.
+    image: Option<File>,
+    sector_count: i32,
+    sector: i32,
+    buffer: [i32; SECTOR_WORDS],
+    position: usize,
+    /// Emulated time left until the running command finishes.
+    busy_time: Option<f64>,
+    error: bool,
+    /// Set when a command finishes. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl Default for DevDisk {
+    fn default() -> Self {
+        DevDisk {
+            image_path: None,
+            image: None,
+            sector_count: 0,
+            sector: 0,
+            buffer: [0; SECTOR_WORDS],
+            position: 0,
+            busy_time: None,
+            error: false,
+            interrupt: false,
+        }
+    }
+}
+
+impl DevDisk {
+    /// Set host image file. Takes effect next time the machine is turned on.
+    pub fn set_image_path(&mut self, path: Option<PathBuf>) {
+        self.image_path = path;
+    }
+
+    /// Advance emulated time. Finishes the running command when its time is up.
+    pub fn update(&mut self, delta_t: f64) {
+        let Some(time) = self.busy_time else {
+            return;
+        };
+        let time = time - delta_t;
+        if time > 0. {
+            self.busy_time = Some(time);
+            return;
+        }
+        self.busy_time = None;
+        self.interrupt = true;
+    }
+
+    fn open_image(&mut self) {
+        self.image = None;
+        self.sector_count = 0;
+        let Some(path) = &self.image_path else {
+            return;
+        };
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("disk: couldn't open {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        self.sector_count = (len / SECTOR_BYTES as u64).min(i32::MAX as u64) as i32;
+        self.image = Some(file);
+    }
+
+    fn command(&mut self, cmd: i32) -> Result<(), ()> {
+        if self.busy_time.is_some() {
+            return Err(());
+        }
+        let result = match cmd {
+            CMD_READ => self.read_sector(),
+            CMD_WRITE => self.write_sector(),
+            _ => return Err(()),
+        };
+        self.error = result.is_err();
+        self.position = 0;
+        self.busy_time = Some(COMMAND_TIME);
+        Ok(())
+    }
+
+    /// Seek the image to the start of selected sector.
+    fn seek(&mut self) -> Result<&mut File, ()> {
+        if !(0..self.sector_count).contains(&self.sector) {
+            return Err(());
+        }
+        let file = self.image.as_mut().ok_or(())?;
+        file.seek(SeekFrom::Start(self.sector as u64 * SECTOR_BYTES as u64)).map_err(|_| ())?;
+        Ok(file)
+    }
+
+    fn read_sector(&mut self) -> Result<(), ()> {
+        let mut bytes = [0; SECTOR_BYTES];
+        self.seek()?.read_exact(&mut bytes).map_err(|_| ())?;
+        for (word, b) in self.buffer.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self) -> Result<(), ()> {
+        let bytes: Vec<u8> = self.buffer.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.seek()?.write_all(&bytes).map_err(|_| ())
+    }
+
+    fn status(&self) -> i32 {
+        let mut status = 0;
+        if self.busy_time.is_some() {
+            status |= STATUS_BUSY;
+        }
+        if self.error {
+            status |= STATUS_ERROR;
+        }
+        if self.image.is_none() {
+            status |= STATUS_NO_DISK;
+        }
+        status
+    }
+
+    /// Stop any running command and clear state. Image file is kept.
+    fn clear(&mut self) {
+        self.sector = 0;
+        self.buffer = [0; SECTOR_WORDS];
+        self.position = 0;
+        self.busy_time = None;
+        self.error = false;
+        self.interrupt = false;
+    }
+}
+
+impl Device for DevDisk {
+    fn reset(&mut self) {
+        self.clear();
+        self.image = None;
+        self.sector_count = 0;
+    }
+    fn on(&mut self) {
+        self.clear();
+        self.open_image();
+    }
+    fn off(&mut self) {
+        self.clear();
+        self.image = None;
+        self.sector_count = 0;
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevDisk {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.status()),
+            1 => Ok(self.sector),
+            2 => {
+                if self.busy_time.is_some() {
+                    return Err(());
+                }
+                let value = self.buffer[self.position];
+                self.position = (self.position + 1) % SECTOR_WORDS;
+                Ok(value)
+            }
+            3 => Ok(self.position as i32),
+            4 => Ok(self.sector_count),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        match port {
+            0 => self.command(value),
+            1 => {
+                self.sector = value;
+                Ok(())
+            }
+            2 => {
+                if self.busy_time.is_some() {
+                    return Err(());
+                }
+                self.buffer[self.position] = value;
+                self.position = (self.position + 1) % SECTOR_WORDS;
+                Ok(())
+            }
+            3 => {
+                self.position = usize::try_from(value).map_err(|_| ())? % SECTOR_WORDS;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn test_dev_disk() -> Result<(), ()> {
+        let path = temp_dir().join("titomachine_test_dev_disk.img");
+        fs::write(&path, vec![0; SECTOR_BYTES * 4]).unwrap();
+
+        let mut disk = DevDisk::default();
+
+        // No disk
+        disk.on();
+        assert_eq!(disk.read_port(0)?, STATUS_NO_DISK);
+        disk.write_port(0, CMD_READ)?;
+        disk.update(COMMAND_TIME);
+        assert_eq!(disk.read_port(0)?, STATUS_NO_DISK | STATUS_ERROR);
+        disk.off();
+
+        disk.set_image_path(Some(path.clone()));
+        disk.on();
+        assert_eq!(disk.read_port(0)?, 0);
+        assert_eq!(disk.read_port(4)?, 4);
+
+        // Test wrong usage
+        assert!(disk.read_port(5).is_err());
+        assert!(disk.write_port(4, 0).is_err());
+        assert!(disk.write_port(0, 3).is_err());
+        assert!(disk.write_port(3, -1).is_err());
+
+        // Fill the buffer, and write it into sector 2.
+        for i in 0..SECTOR_WORDS as i32 {
+            disk.write_port(2, i * -1000)?;
+        }
+        disk.write_port(1, 2)?;
+        disk.write_port(0, CMD_WRITE)?;
+        assert_eq!(disk.read_port(0)?, STATUS_BUSY);
+        assert!(disk.write_port(0, CMD_READ).is_err());
+        assert!(disk.read_port(2).is_err());
+        disk.update(COMMAND_TIME / 2.);
+        assert_eq!(disk.read_port(0)?, STATUS_BUSY);
+        assert!(!disk.interrupt);
+        disk.update(COMMAND_TIME / 2.);
+        assert_eq!(disk.read_port(0)?, 0);
+        assert!(disk.interrupt);
+
+        // Read sector 1: still empty
+        disk.write_port(1, 1)?;
+        disk.write_port(0, CMD_READ)?;
+        disk.update(COMMAND_TIME);
+        assert_eq!(disk.read_port(2)?, 0);
+
+        // Power cycle and read sector 2 back.
+        disk.off();
+        disk.on();
+        disk.write_port(1, 2)?;
+        disk.write_port(0, CMD_READ)?;
+        disk.update(COMMAND_TIME);
+        assert_eq!(disk.read_port(0)?, 0);
+        assert_eq!(disk.read_port(2)?, 0);
+        assert_eq!(disk.read_port(2)?, -1000);
+        disk.write_port(3, 127)?;
+        assert_eq!(disk.read_port(2)?, -127000);
+        assert_eq!(disk.read_port(3)?, 0);
+
+        // Out of range sector
+        disk.write_port(1, 4)?;
+        disk.write_port(0, CMD_READ)?;
+        disk.update(COMMAND_TIME);
+        assert_eq!(disk.read_port(0)?, STATUS_ERROR);
+
+        // It's in the file, too.
+        disk.off();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[SECTOR_BYTES * 2 + 4..SECTOR_BYTES * 2 + 8], &(-1000i32).to_le_bytes());
+
+        let _ = fs::remove_file(&path);
+        Ok(())
+    }
+}
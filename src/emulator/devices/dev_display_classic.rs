@@ -1,19 +1,111 @@
 //!
 //! Memory mapped framebuffer
 //!
-use super::{Device, MMIO};
-use image::Rgba;
+//! Color screen with a framebuffer at 0x2000-0x6aff, and palette RAM at 0x6c00-0x6cff. In its
+//! default mode it's 160x120, and displays the image identically to titokone.
+//!
+//! The screen refreshes at a configurable rate (60 Hz by default) in emulated time. At the end of
+//! each frame there's a vertical blank period, during which the finished frame is sent to the
+//! Graphics panel. Drawing during VBlank, or between VBlanks, doesn't tear. Pausing or stepping
+//! the emulator also sends the current image, so drawing can be followed while debugging.
+//!
+//! The start of VBlank raises the VBlank interrupt (PIC bit 7, IVT entry 4), if it's enabled in
+//! the PIC.
+//!
+//! ### Modes
+//! | Mode | Resolution | Colors         | Framebuffer                                                |
+//! | ---- | ---------- | -------------- | ---------------------------------------------------------- |
+//! | 0    | 160x120    | Direct, 12-bit | One pixel per word (titokone compatible, default)          |
+//! | 1    | 160x120    | Indexed, 256   | One pixel per word, palette index in the lowest 8 bits     |
+//! | 2    | 320x240    | Indexed, 256   | Four pixels per word, leftmost pixel in the highest 8 bits |
+//!
+//! All modes use the same 19200 words of memory. Pixel `(x, y)` of mode 2 is in word
+//! `0x2000 + (y * 320 + x) / 4`. Changing the mode doesn't clear the framebuffer.
+//!
+//! ### Palette
+//! 256 colors at 0x6c00-0x6cff, as 24-bit `0xRRGGBB` values. The palette is reset at power on
+//! to 16 standard colors, a 6x6x6 color cube and a grayscale ramp.
+//!
+//! ### Double buffering
+//! There are two framebuffer pages. The draw page is the one that is accessed through memory, and
+//! the shown page is the one on screen. Both are page 0 at power on, so by default everything is
+//! drawn straight on screen like on titokone.
+//!
+//! Flipping shows the draw page, and makes the other page the new draw page. A program can draw a
+//! whole frame on the hidden page, then flip, and the screen never shows a half drawn frame.
+//! Flipping at VBlank waits for the current frame to finish first.
+//!
+//! Ports:
+//!  - Port 0: Status (global port 0x80)
+//!  - Port 1: Frame counter (global port 0x81)
+//!  - Port 2: Page (global port 0x82)
+//!  - Port 3: Flip (global port 0x83)
+//!  - Port 4: Mode (global port 0x84)
+//!
+//! Status bits:
+//! | Bit | Meaning                                   |
+//! | --- | ----------------------------------------- |
+//! | 0   | VBlank: the frame is finished             |
+//! | 1   | Flip pending: waiting for VBlank to flip  |
+//!
+//! Page bits:
+//! | Bit | Meaning                                   |
+//! | --- | ----------------------------------------- |
+//! | 0   | Draw page                                 |
+//! | 1   | Shown page                                |
+//!
+//! | Port | Read                                   | Write                                         |
+//! | ---- | -------------------------------------- | --------------------------------------------- |
+//! | 0    | Status                                 | Error                                         |
+//! | 1    | Number of frames since power on        | Error                                         |
+//! | 2    | Page                                   | Select draw page (0 or 1)                     |
+//! | 3    | Error                                  | 0: Flip now, 1: Flip at next VBlank           |
+//! | 4    | Mode                                   | Set mode (0-2)                                |
+//!
+use super::{Device, MMIO, PMIO};
+use image::{Rgba, RgbaImage};
 use std::sync::mpsc::Sender;
 
+/// Framebuffer size in words
+const PAGE_WORDS: usize = 160 * 120;
+/// Palette RAM location, relative to the framebuffer.
+const ADDR_PALETTE: usize = 0x4c00;
+const PALETTE_LEN: usize = 256;
+const DEFAULT_REFRESH_RATE: f64 = 60.;
+/// Portion of the frame time that is vertical blank.
+const VBLANK_FRACTION: f64 = 0.08;
+
+const STATUS_VBLANK: i32 = 0b_01;
+const STATUS_FLIP_PENDING: i32 = 0b_10;
+
+const FLIP_NOW: i32 = 0;
+const FLIP_AT_VBLANK: i32 = 1;
+
+const MODE_CLASSIC: i32 = 0;
+const MODE_INDEXED: i32 = 1;
+const MODE_INDEXED_HIRES: i32 = 2;
+
 /// Color screen with memory mapped framebuffer
-/// It displays the image identically to titokone.
-///
-/// 160x120
+/// In classic mode it displays the image identically to titokone.
 pub(crate) struct DevDisplayClassic {
-    /// mpsc sender for framebuf
-    tx: Option<Sender<Vec<Rgba<u8>>>>,
-    framebuffer: Vec<Rgba<u8>>,
-    /// Interrupt signal
+    /// mpsc sender for framebuf, with the number of the frame it was captured at
+    tx: Option<Sender<(i32, RgbaImage)>>,
+    pages: [Vec<i32>; 2],
+    draw_page: usize,
+    shown_page: usize,
+    flip_pending: bool,
+    mode: i32,
+    palette: Vec<i32>,
+    /// Finished frame that hasn't been sent yet, and its frame number.
+    pending: Option<(i32, RgbaImage)>,
+    refresh_rate: f64,
+    /// Emulated time since the frame started.
+    frame_time: f64,
+    vblank: bool,
+    frame_count: i32,
+    /// Set when a frame is finished. Bus composites it and passes it back with `finish_frame()`.
+    pub(crate) frame_done: bool,
+    /// Set at the start of VBlank. Bus passes this on to PIC.
     pub(crate) interrupt: bool,
 }
 
@@ -21,7 +113,18 @@ impl Default for DevDisplayClassic {
     fn default() -> Self {
         Self {
             tx: None,
-            framebuffer: vec![Rgba([0, 0, 0, 255, ]); 120 * 160],
+            pages: [vec![0; PAGE_WORDS], vec![0; PAGE_WORDS]],
+            draw_page: 0,
+            shown_page: 0,
+            flip_pending: false,
+            mode: MODE_CLASSIC,
+            palette: default_palette(),
+            pending: None,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            frame_time: 0.,
+            vblank: false,
+            frame_count: 0,
+            frame_done: false,
             interrupt: false,
         }
     }
@@ -29,45 +132,295 @@ impl Default for DevDisplayClassic {
 
 impl Device for DevDisplayClassic {
     fn reset(&mut self) {
-        self.framebuffer = vec![Rgba([0, 0, 0, 255, ]); 120 * 160];
-        self.interrupt = false
+        self.clear_pages();
+        self.clear_timing();
+    }
+    fn on(&mut self) {
+        self.clear_timing();
     }
-    fn on(&mut self) {}
     fn off(&mut self) {
-        self.framebuffer = vec![Rgba([0, 0, 0, 255, ]); 120 * 160];
-        self.interrupt = false
+        self.clear_pages();
+        self.clear_timing();
     }
     fn set_pause(&mut self, _paused: bool) {}
 }
 
 impl DevDisplayClassic {
     /// Give the device an mpsc sender to send framebuffer to.
-    pub fn connect(&mut self, tx: Sender<Vec<Rgba<u8>>>) {
+    pub fn connect(&mut self, tx: Sender<(i32, RgbaImage)>) {
         self.tx = Some(tx);
     }
-    /// Send framebuffer
+    /// Set refresh rate in Hz.
+    pub fn set_refresh_rate(&mut self, rate: f32) {
+        self.refresh_rate = (rate as f64).max(1.);
+    }
+    /// Advance emulated time. Starts and ends VBlank.
+    pub fn update(&mut self, delta_t: f64) {
+        let period = 1. / self.refresh_rate;
+        self.frame_time += delta_t;
+        loop {
+            if !self.vblank && self.frame_time >= period * (1. - VBLANK_FRACTION) {
+                if std::mem::take(&mut self.flip_pending) {
+                    self.flip();
+                }
+                self.vblank = true;
+                self.frame_count = self.frame_count.wrapping_add(1);
+                self.frame_done = true;
+                self.interrupt = true;
+            }
+            if self.vblank && self.frame_time >= period {
+                self.vblank = false;
+                self.frame_time -= period;
+                continue;
+            }
+            break;
+        }
+    }
+    /// Image of the shown page, for compositing other video devices on top.
+    pub(crate) fn frame(&self) -> RgbaImage {
+        let page = &self.pages[self.shown_page];
+        match self.mode {
+            MODE_INDEXED => RgbaImage::from_fn(160, 120, |x, y| {
+                let word = page[(y * 160 + x) as usize];
+                self.palette_color(word)
+            }),
+            MODE_INDEXED_HIRES => RgbaImage::from_fn(320, 240, |x, y| {
+                let idx = (y * 320 + x) as usize;
+                let word = page[idx / 4];
+                self.palette_color(word >> (8 * (3 - idx % 4)))
+            }),
+            _ => RgbaImage::from_fn(160, 120, |x, y| decode_color(page[(y * 160 + x) as usize])),
+        }
+    }
+    /// Framebuffer word of the shown page. Memory reads see the draw page instead.
+    pub(crate) fn read_shown(&self, addr: usize) -> Result<i32, ()> {
+        self.pages[self.shown_page].get(addr).copied().ok_or(())
+    }
+    fn palette_color(&self, index: i32) -> Rgba<u8> {
+        let [_, r, g, b] = self.palette[index as usize & 0xff].to_be_bytes();
+        Rgba([r, g, b, 255])
+    }
+    /// Show the draw page, and draw on the other one.
+    fn flip(&mut self) {
+        self.shown_page = self.draw_page;
+        self.draw_page = 1 - self.draw_page;
+    }
+    /// Store a finished frame until it's sent. It's numbered with the frame counter, so the
+    /// receiver can tell how much emulated time has passed, even if frames were skipped.
+    pub(crate) fn finish_frame(&mut self, frame: RgbaImage) {
+        self.pending = Some((self.frame_count, frame));
+    }
+    /// Send finished frame, if there is one.
     pub(crate) fn send(&mut self) {
-        self.interrupt = true;
+        let Some(frame) = self.pending.take() else {
+            return;
+        };
         if let Some(tx) = &self.tx {
-            let _ = tx.send(self.framebuffer.clone());
+            let _ = tx.send(frame);
+        }
+    }
+    fn clear_pages(&mut self) {
+        self.pages = [vec![0; PAGE_WORDS], vec![0; PAGE_WORDS]];
+        self.draw_page = 0;
+        self.shown_page = 0;
+        self.flip_pending = false;
+        self.mode = MODE_CLASSIC;
+        self.palette = default_palette();
+    }
+    fn clear_timing(&mut self) {
+        self.pending = None;
+        self.frame_time = 0.;
+        self.vblank = false;
+        self.frame_count = 0;
+        self.frame_done = false;
+        self.interrupt = false;
+    }
+}
+
+/// Convert a color value, as written by a program, into a pixel.
+pub(crate) fn decode_color(value: i32) -> Rgba<u8> {
+    Rgba([(value >> 4) as u8, value as u8, (value << 4) as u8, 255])
+}
+
+/// 16 standard colors, 6x6x6 color cube, and 24 grays.
+fn default_palette() -> Vec<i32> {
+    const STANDARD: [i32; 16] = [
+        0x000000, 0x800000, 0x008000, 0x808000, 0x000080, 0x800080, 0x008080, 0xc0c0c0,
+        0x808080, 0xff0000, 0x00ff00, 0xffff00, 0x0000ff, 0xff00ff, 0x00ffff, 0xffffff,
+    ];
+    const CUBE_LEVELS: [i32; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+    let mut palette = Vec::with_capacity(PALETTE_LEN);
+    palette.extend(STANDARD);
+    for r in CUBE_LEVELS {
+        for g in CUBE_LEVELS {
+            for b in CUBE_LEVELS {
+                palette.push(r << 16 | g << 8 | b);
+            }
         }
     }
+    for i in 0..24 {
+        let level = 8 + i * 10;
+        palette.push(level << 16 | level << 8 | level);
+    }
+    palette
 }
 
 impl MMIO for DevDisplayClassic {
     fn read(&mut self, addr: usize) -> Result<i32, ()> {
-        if addr >= self.framebuffer.len() {
-            return Err(());
+        match addr {
+            ADDR_PALETTE.. => self.palette.get(addr - ADDR_PALETTE).copied().ok_or(()),
+            _ => self.pages[self.draw_page].get(addr).copied().ok_or(()),
         }
-        let color = self.framebuffer[addr];
-        Ok((color[0] << 4) as i32 + color[1] as i32 + (color[2] >> 4) as i32)
     }
     fn write(&mut self, addr: usize, value: i32) -> Result<(), ()> {
-        if addr >= self.framebuffer.len() {
-            return Err(());
+        let word = match addr {
+            ADDR_PALETTE.. => self.palette.get_mut(addr - ADDR_PALETTE),
+            _ => self.pages[self.draw_page].get_mut(addr),
+        };
+        *word.ok_or(())? = value;
+        Ok(())
+    }
+}
+
+impl PMIO for DevDisplayClassic {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => {
+                let mut status = 0;
+                if self.vblank {
+                    status |= STATUS_VBLANK;
+                }
+                if self.flip_pending {
+                    status |= STATUS_FLIP_PENDING;
+                }
+                Ok(status)
+            }
+            1 => Ok(self.frame_count),
+            2 => Ok((self.draw_page | self.shown_page << 1) as i32),
+            4 => Ok(self.mode),
+            _ => Err(()),
         }
-        let color = Rgba([(value >> 4) as u8, value as u8, (value << 4) as u8, 255]);
-        self.framebuffer[addr] = color;
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        match port {
+            2 => match value {
+                0 | 1 => self.draw_page = value as usize,
+                _ => return Err(()),
+            },
+            3 => match value {
+                FLIP_NOW => self.flip(),
+                FLIP_AT_VBLANK => self.flip_pending = true,
+                _ => return Err(()),
+            },
+            4 => match value {
+                MODE_CLASSIC | MODE_INDEXED | MODE_INDEXED_HIRES => self.mode = value,
+                _ => return Err(()),
+            },
+            _ => return Err(()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_dev_display_classic() -> Result<(), ()> {
+        let (tx, rx) = mpsc::channel();
+        let mut display = DevDisplayClassic::default();
+        display.connect(tx);
+        display.set_refresh_rate(50.);
+        display.on();
+
+        // Test wrong usage
+        assert!(display.read(PAGE_WORDS).is_err());
+        assert!(display.write(PAGE_WORDS, 0).is_err());
+        assert!(display.write(ADDR_PALETTE + PALETTE_LEN, 0).is_err());
+        assert!(display.read_port(5).is_err());
+        assert!(display.write_port(0, 0).is_err());
+        assert!(display.write_port(2, 2).is_err());
+        assert!(display.write_port(3, 2).is_err());
+        assert!(display.write_port(4, 3).is_err());
+        assert!(display.read_port(3).is_err());
+
+        display.write(0, 0x0fff)?;
+        assert_eq!(display.read(0)?, 0x0fff);
+
+        // 50 Hz: a frame is 20 ms, of which VBlank is the last 1.6 ms.
+        display.update(0.018);
+        assert_eq!(display.read_port(0)?, 0);
+        assert!(!display.interrupt);
+        display.update(0.001);
+        assert_eq!(display.read_port(0)?, STATUS_VBLANK);
+        assert_eq!(display.read_port(1)?, 1);
+        assert!(std::mem::take(&mut display.interrupt));
+        assert!(std::mem::take(&mut display.frame_done));
+        display.update(0.0015);
+        assert_eq!(display.read_port(0)?, 0);
+
+        // Nothing is sent until there's a finished frame.
+        display.send();
+        assert!(rx.try_recv().is_err());
+        display.finish_frame(display.frame());
+        assert!(display.pending.is_some());
+        display.send();
+        assert!(display.pending.is_none());
+        let (number, frame) = rx.try_recv().unwrap();
+        assert_eq!(number, 1);
+        assert_eq!(*frame.get_pixel(0, 0), decode_color(0x0fff));
+
+        // Long time steps still count every frame.
+        display.update(0.1);
+        assert_eq!(display.read_port(1)?, 6);
+
+        // Double buffering: draw on page 1 while page 0 is shown.
+        display.write_port(3, FLIP_NOW)?;
+        assert_eq!(display.read_port(2)?, 0b_01);
+        display.write(0, 0x0f00)?;
+        assert_eq!(*display.frame().get_pixel(0, 0), decode_color(0x0fff));
+        assert_eq!(display.read(0)?, 0x0f00);
+        assert_eq!(display.read_shown(0)?, 0x0fff);
+        // Flip at VBlank waits for the frame to finish.
+        display.write_port(3, FLIP_AT_VBLANK)?;
+        assert_eq!(display.read_port(0)?, STATUS_FLIP_PENDING);
+        display.update(0.01);
+        assert_eq!(*display.frame().get_pixel(0, 0), decode_color(0x0fff));
+        display.update(0.009);
+        assert_eq!(display.read_port(0)?, STATUS_VBLANK);
+        assert_eq!(display.read_port(2)?, 0b_10);
+        assert_eq!(*display.frame().get_pixel(0, 0), decode_color(0x0f00));
+
+        // Indexed mode. Page 1 is shown, draw on it too.
+        display.write_port(2, 1)?;
+        display.write_port(4, MODE_INDEXED)?;
+        assert_eq!(display.read_port(4)?, MODE_INDEXED);
+        assert_eq!(display.read(ADDR_PALETTE + 9)?, 0xff0000);
+        display.write(ADDR_PALETTE + 1, 0x123456)?;
+        display.write(161, 1)?;
+        display.write(162, 9)?;
+        let frame = display.frame();
+        assert_eq!(frame.dimensions(), (160, 120));
+        assert_eq!(*frame.get_pixel(1, 1), Rgba([0x12, 0x34, 0x56, 255]));
+        assert_eq!(*frame.get_pixel(2, 1), Rgba([0xff, 0, 0, 255]));
+
+        // 320x240: four pixels per word.
+        display.write_port(4, MODE_INDEXED_HIRES)?;
+        display.write(80, 0x0001090f)?; // Second row, first four pixels
+        let frame = display.frame();
+        assert_eq!(frame.dimensions(), (320, 240));
+        assert_eq!(*frame.get_pixel(0, 1), Rgba([0, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(1, 1), Rgba([0x12, 0x34, 0x56, 255]));
+        assert_eq!(*frame.get_pixel(2, 1), Rgba([0xff, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(3, 1), Rgba([0xff, 0xff, 0xff, 255]));
+
+        // Power off returns to classic mode and default palette.
+        display.off();
+        assert_eq!(display.read_port(4)?, MODE_CLASSIC);
+        assert_eq!(display.read(ADDR_PALETTE + 1)?, 0x800000);
+
         Ok(())
     }
 }
@@ -1,3 +1,269 @@
 //!
-//! This will become the MIDI port.
+//! MIDI port
 //!
+//! Takes a raw MIDI byte stream, one byte per `OUT`. Messages are played with a built-in
+//! SoundFont synth, and can also be recorded into a .mid file.
+//!
+//! Ports:
+//!  - Port 0: MIDI out (global port 0x30)
+//!
+//! | Port | Read  | Write                 |
+//! | ---- | ----- | --------------------- |
+//! | 0    | Error | Send one byte (0-255) |
+//!
+//! Channel messages (0x80-0xEF) are supported, including running status. System messages are
+//! ignored. See [synth] for what the synth does with them.
+//!
+//! Example: play middle C on channel 1 at velocity 100, and release it.
+//! ```text
+//! 0x90, 60, 100
+//! 0x80, 60, 0
+//! ```
+//!
+//! When a recording file is set, everything sent while the machine is on is written into it when
+//! the machine is turned off. Timing comes from emulated time, not host time.
+//!
+
+use super::dev_psg::AudioSource;
+use super::{Device, PMIO};
+use rodio::{OutputStream, Sink};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+mod smf;
+mod soundfont;
+mod synth;
+
+use self::smf::SmfRecorder;
+use self::soundfont::SoundFont;
+use self::synth::MidiSynth;
+
+/// Built-in SoundFont. See midi/readme.md for license.
+const SOUNDFONT: &[u8] = include_bytes!("midi/sounds.sf2");
+
+/// MIDI port
+pub(crate) struct DevMIDI {
+    #[allow(dead_code)] // Output stream is never "used", but we have to keep it around in order to
+    // get sound.
+    stream: Option<OutputStream>,
+    sink: Sink,
+    synth: Arc<Mutex<MidiSynth>>,
+    /// Status byte of the message being received. Kept for running status.
+    status: Option<u8>,
+    /// Data bytes received so far.
+    data: Vec<u8>,
+    record_path: Option<PathBuf>,
+    recording: Option<SmfRecorder>,
+    /// Emulated time in seconds. Kept up to date by the Bus.
+    time: f64,
+    /// Emulated time when recording started.
+    record_start: f64,
+}
+
+impl Default for DevMIDI {
+    fn default() -> Self {
+        let soundfont = match SoundFont::parse(SOUNDFONT) {
+            Ok(soundfont) => soundfont,
+            Err(e) => panic!("Built-in SoundFont is broken: {}", e),
+        };
+        let synth = Arc::new(Mutex::new(MidiSynth::new(Arc::new(soundfont))));
+
+        let (stream, sink) = match OutputStream::try_default() {
+            // Host has an audio device, create sink.
+            Ok((stream, stream_handle)) => (Some(stream), Sink::try_new(&stream_handle).unwrap()),
+            // No audio devices available. Create a sink that does nothing.
+            Err(_) => (None, Sink::new_idle().0),
+        };
+        sink.pause();
+        sink.append(AudioSource::new(synth.clone()));
+
+        DevMIDI {
+            stream,
+            sink,
+            synth,
+            status: None,
+            data: Vec::new(),
+            record_path: None,
+            recording: None,
+            time: 0.,
+            record_start: 0.,
+        }
+    }
+}
+
+impl DevMIDI {
+    /// Set host file for recording. Takes effect next time the machine is turned on.
+    pub fn set_record_path(&mut self, path: Option<PathBuf>) {
+        self.record_path = path;
+    }
+
+    /// Update emulated time. Used for recording timestamps.
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    /// Receive one byte of the MIDI stream.
+    fn receive(&mut self, byte: u8) {
+        match byte {
+            // Channel message status
+            0x80..=0xef => {
+                self.status = Some(byte);
+                self.data.clear();
+            }
+            // Realtime messages may appear anywhere, and don't affect running status.
+            0xf8..=0xff => (),
+            // Other system messages cancel running status.
+            0xf0..=0xf7 => {
+                self.status = None;
+                self.data.clear();
+            }
+            // Data byte
+            _ => {
+                let Some(status) = self.status else {
+                    return;
+                };
+                self.data.push(byte);
+                if self.data.len() == data_len(status) {
+                    self.send_message(status);
+                    self.data.clear();
+                }
+            }
+        }
+    }
+
+    fn send_message(&mut self, status: u8) {
+        self.synth.lock().unwrap().message(status, &self.data);
+        if let Some(recording) = &mut self.recording {
+            let mut message = vec![status];
+            message.extend_from_slice(&self.data);
+            recording.record(self.time - self.record_start, &message);
+        }
+    }
+
+    /// Write recording into the file, if there is one.
+    fn finish_recording(&mut self) {
+        let (Some(recording), Some(path)) = (self.recording.take(), &self.record_path) else {
+            return;
+        };
+        if let Err(e) = fs::write(path, recording.to_bytes()) {
+            println!("midi: couldn't write {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Number of data bytes in a channel message
+fn data_len(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+impl Device for DevMIDI {
+    fn reset(&mut self) {
+        self.synth.lock().unwrap().reset();
+        self.status = None;
+        self.data.clear();
+        self.recording = None;
+    }
+    fn on(&mut self) {
+        self.synth.lock().unwrap().reset();
+        self.status = None;
+        self.data.clear();
+        if self.record_path.is_some() {
+            self.recording = Some(SmfRecorder::default());
+            self.record_start = self.time;
+        }
+        self.sink.play();
+    }
+    fn off(&mut self) {
+        self.synth.lock().unwrap().all_notes_off();
+        self.finish_recording();
+        self.sink.pause();
+    }
+    fn set_pause(&mut self, paused: bool) {
+        if paused {
+            self.sink.pause();
+        } else {
+            self.sink.play();
+        }
+    }
+}
+
+impl PMIO for DevMIDI {
+    fn read_port(&mut self, _port: u8) -> Result<i32, ()> {
+        Err(()) // You can't read from MIDI out!
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        if port != 0 {
+            return Err(());
+        }
+        let byte = u8::try_from(value).map_err(|_| ())?;
+        self.receive(byte);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::devices::dev_psg::AudioChannel;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_dev_midi() -> Result<(), ()> {
+        let path = temp_dir().join("titomachine_test_dev_midi.mid");
+        let _ = fs::remove_file(&path);
+
+        let mut midi = DevMIDI::default();
+
+        // Test wrong usage
+        assert!(midi.read_port(0).is_err());
+        assert!(midi.write_port(1, 0x90).is_err());
+        assert!(midi.write_port(0, 256).is_err());
+        assert!(midi.write_port(0, -1).is_err());
+
+        // Turning off without a recording doesn't create a file.
+        midi.off();
+        assert!(!path.exists());
+
+        midi.set_record_path(Some(path.clone()));
+        midi.set_time(10.);
+        midi.on();
+
+        // Note on, and the synth makes sound.
+        for byte in [0x90, 60, 100] {
+            midi.write_port(0, byte)?;
+        }
+        let synth = midi.synth.clone();
+        let loudest = (0..2000)
+            .map(|_| synth.lock().unwrap().get_next_sample().abs())
+            .fold(0., f32::max);
+        assert!(loudest > 0.01);
+
+        // Half a second later: running status note off (velocity 0), with a realtime byte in between.
+        midi.set_time(10.5);
+        for byte in [60, 0xf8, 0] {
+            midi.write_port(0, byte)?;
+        }
+        midi.off();
+
+        #[rustfmt::skip]
+        let expected_track = [
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, // Tempo
+            0x00, 0x90, 60, 100,                      // Note on at 0
+            0x83, 0x60, 0x90, 60, 0,                  // Note off at 480 ticks
+            0x00, 0xff, 0x2f, 0x00,                   // End of track
+        ];
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[0..4], b"MThd");
+        assert_eq!(&bytes[8..14], &[0, 0, 0, 1, 0x01, 0xe0]);
+        assert_eq!(&bytes[14..18], b"MTrk");
+        assert_eq!(&bytes[18..22], &(expected_track.len() as u32).to_be_bytes());
+        assert_eq!(&bytes[22..], &expected_track);
+
+        let _ = fs::remove_file(&path);
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+//!
+//! Standard MIDI File writer for recording MIDI output.
+//!
+//! The recording is a format 0 file with a single track. Event times come from emulated time, so
+//! the file plays back at the speed the program would run on the real thing.
+//!
+
+/// Ticks per quarter note
+const DIVISION: u16 = 480;
+/// Microseconds per quarter note. 120 bpm.
+const TEMPO: u32 = 500_000;
+const TICKS_PER_SECOND: f64 = DIVISION as f64 * 1_000_000. / TEMPO as f64;
+
+/// Collects MIDI messages with timestamps, and turns them into a .mid file.
+#[derive(Default)]
+pub(crate) struct SmfRecorder {
+    /// Track data, without the header and end of track.
+    track: Vec<u8>,
+    /// Time of last event in ticks.
+    last_tick: u64,
+}
+
+impl SmfRecorder {
+    /// Add a complete message. `time` is in seconds from the start of recording.
+    pub fn record(&mut self, time: f64, message: &[u8]) {
+        let tick = ((time.max(0.) * TICKS_PER_SECOND) as u64).max(self.last_tick);
+        write_vlq(&mut self.track, (tick - self.last_tick) as u32);
+        self.track.extend_from_slice(message);
+        self.last_tick = tick;
+    }
+
+    /// Build the complete file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut track = Vec::new();
+        // Tempo meta event
+        track.extend_from_slice(&[0x00, 0xff, 0x51, 0x03]);
+        track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+        track.extend_from_slice(&self.track);
+        // End of track
+        track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes()); // Format 0
+        bytes.extend_from_slice(&1u16.to_be_bytes()); // 1 track
+        bytes.extend_from_slice(&DIVISION.to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+        bytes
+    }
+}
+
+/// Variable length quantity: 7 bits per byte, most significant first, high bit set on all but last.
+fn write_vlq(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.iter().rev());
+}
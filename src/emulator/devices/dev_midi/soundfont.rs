@@ -0,0 +1,343 @@
+//!
+//! SoundFont 2 reader
+//!
+//! Only what the synth needs is read: sample data, presets, instruments, and a handful of
+//! generators. Modulators are ignored.
+//!
+//! Preset zones and instrument zones are flattened into [Region]s at load time, so the synth never
+//! has to walk the preset -> instrument -> sample hierarchy while playing.
+//!
+
+use std::collections::HashMap;
+
+// Generator operators that we care about. The rest are ignored.
+const GEN_START_OFFSET: u16 = 0;
+const GEN_END_OFFSET: u16 = 1;
+const GEN_STARTLOOP_OFFSET: u16 = 2;
+const GEN_ENDLOOP_OFFSET: u16 = 3;
+const GEN_START_COARSE_OFFSET: u16 = 4;
+const GEN_END_COARSE_OFFSET: u16 = 12;
+const GEN_ATTACK_VOL_ENV: u16 = 34;
+const GEN_HOLD_VOL_ENV: u16 = 35;
+const GEN_DECAY_VOL_ENV: u16 = 36;
+const GEN_SUSTAIN_VOL_ENV: u16 = 37;
+const GEN_RELEASE_VOL_ENV: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_VEL_RANGE: u16 = 44;
+const GEN_STARTLOOP_COARSE_OFFSET: u16 = 45;
+const GEN_INITIAL_ATTENUATION: u16 = 48;
+const GEN_ENDLOOP_COARSE_OFFSET: u16 = 50;
+const GEN_COARSE_TUNE: u16 = 51;
+const GEN_FINE_TUNE: u16 = 52;
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+const GEN_OVERRIDING_ROOT_KEY: u16 = 58;
+const GEN_COUNT: usize = 61;
+
+/// Envelope times are in timecents. This is the spec default, and means "instant".
+const TIMECENTS_INSTANT: i16 = -12000;
+
+/// One playable piece of a preset: a sample with its key / velocity range and playback settings.
+#[derive(Clone)]
+pub(crate) struct Region {
+    pub key_lo: u8,
+    pub key_hi: u8,
+    pub vel_lo: u8,
+    pub vel_hi: u8,
+    /// Sample data range in [SoundFont::samples]. End is exclusive.
+    pub start: usize,
+    pub end: usize,
+    pub loop_start: usize,
+    pub loop_end: usize,
+    pub looping: bool,
+    pub sample_rate: u32,
+    /// Key at which the sample plays at its original pitch.
+    pub root_key: i32,
+    /// Additional tuning in semitones.
+    pub tune: f32,
+    /// Gain from initial attenuation. Range: 0.0 to 1.0
+    pub gain: f32,
+    /// Volume envelope times in seconds.
+    pub attack: f32,
+    pub hold: f32,
+    pub decay: f32,
+    pub release: f32,
+    /// Volume envelope sustain level. Range: 0.0 to 1.0
+    pub sustain: f32,
+}
+
+/// Parsed SoundFont.
+pub(crate) struct SoundFont {
+    /// All sample data. 16-bit mono.
+    pub samples: Vec<i16>,
+    /// Regions by (bank, program).
+    presets: HashMap<(u16, u16), Vec<Region>>,
+}
+
+/// Generator values of a zone. None means not set.
+#[derive(Clone, Copy)]
+struct Zone {
+    gens: [Option<i16>; GEN_COUNT],
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Zone { gens: [None; GEN_COUNT] }
+    }
+}
+
+impl Zone {
+    fn get(&self, op: u16) -> Option<i16> {
+        self.gens.get(op as usize).copied().flatten()
+    }
+    fn get_or(&self, op: u16, default: i16) -> i16 {
+        self.get(op).unwrap_or(default)
+    }
+    /// Range generators are two bytes: lo, hi.
+    fn range(&self, op: u16) -> (u8, u8) {
+        match self.get(op) {
+            Some(value) => (value as u16 as u8, (value as u16 >> 8) as u8),
+            None => (0, 127),
+        }
+    }
+    /// Values that are set in this zone override the ones in global zone.
+    fn merged_over(&self, global: &Zone) -> Zone {
+        let mut zone = *global;
+        for (op, value) in self.gens.iter().enumerate() {
+            if value.is_some() {
+                zone.gens[op] = *value;
+            }
+        }
+        zone
+    }
+}
+
+/// Sample header
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+}
+
+impl SoundFont {
+    /// Parse a SoundFont 2 file.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+            return Err("not a SoundFont 2 file".into());
+        }
+
+        // Collect the chunks we need from the LIST chunks.
+        let mut chunks: HashMap<[u8; 4], &[u8]> = HashMap::new();
+        for (id, list) in riff_chunks(&data[12..]) {
+            if &id != b"LIST" || list.len() < 4 {
+                continue;
+            }
+            for (sub_id, sub) in riff_chunks(&list[4..]) {
+                chunks.insert(sub_id, sub);
+            }
+        }
+        let chunk = |id: &[u8; 4]| chunks.get(id).copied().ok_or(format!("missing chunk {}", String::from_utf8_lossy(id)));
+
+        let samples = chunk(b"smpl")?
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        // Sample headers
+        let headers: Vec<SampleHeader> = chunk(b"shdr")?
+            .chunks_exact(46)
+            .map(|b| SampleHeader {
+                start: read_u32(b, 20),
+                end: read_u32(b, 24),
+                loop_start: read_u32(b, 28),
+                loop_end: read_u32(b, 32),
+                sample_rate: read_u32(b, 36),
+                original_pitch: b[40],
+                pitch_correction: b[41] as i8,
+            })
+            .collect();
+
+        // Instruments
+        let inst_bag_idx: Vec<usize> = chunk(b"inst")?.chunks_exact(22).map(|b| read_u16(b, 20) as usize).collect();
+        let inst_zones = read_zones(chunk(b"ibag")?, chunk(b"igen")?);
+        let pre_bag_idx: Vec<(u16, u16, usize)> = chunk(b"phdr")?
+            .chunks_exact(38)
+            .map(|b| (read_u16(b, 22), read_u16(b, 20), read_u16(b, 24) as usize))
+            .collect();
+        let pre_zones = read_zones(chunk(b"pbag")?, chunk(b"pgen")?);
+
+        // Flatten presets. The last header of phdr and inst is a terminator, hence windows(2).
+        let mut presets = HashMap::new();
+        for pair in pre_bag_idx.windows(2) {
+            let (bank, program, bag_start) = pair[0];
+            let bag_end = pair[1].2;
+            let Some(zones) = pre_zones.get(bag_start..bag_end) else {
+                continue;
+            };
+            let (pre_global, zones) = split_global(zones, GEN_INSTRUMENT);
+            let mut regions = Vec::new();
+            for pzone in zones {
+                let pzone = pzone.merged_over(&pre_global);
+                let Some(inst) = pzone.get(GEN_INSTRUMENT) else {
+                    continue;
+                };
+                let inst = inst as u16 as usize;
+                let (Some(&ibag_start), Some(&ibag_end)) = (inst_bag_idx.get(inst), inst_bag_idx.get(inst + 1)) else {
+                    continue;
+                };
+                let Some(izones) = inst_zones.get(ibag_start..ibag_end) else {
+                    continue;
+                };
+                let (inst_global, izones) = split_global(izones, GEN_SAMPLE_ID);
+                for izone in izones {
+                    let izone = izone.merged_over(&inst_global);
+                    if let Some(region) = make_region(&pzone, &izone, &headers) {
+                        regions.push(region);
+                    }
+                }
+            }
+            presets.insert((bank, program), regions);
+        }
+
+        Ok(SoundFont { samples, presets })
+    }
+
+    /// Find regions that should play for a note. Falls back to bank 0 and then to the first
+    /// program of the bank if the exact preset doesn't exist.
+    pub fn regions(&self, bank: u16, program: u16, key: u8, vel: u8) -> Vec<Region> {
+        let regions = self.presets.get(&(bank, program))
+            .or_else(|| self.presets.get(&(if bank == 128 { 128 } else { 0 }, program)))
+            .or_else(|| self.presets.get(&(bank, 0)));
+        match regions {
+            Some(regions) => regions
+                .iter()
+                .filter(|r| (r.key_lo..=r.key_hi).contains(&key) && (r.vel_lo..=r.vel_hi).contains(&vel))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Combine a preset zone and an instrument zone into a region. Returns None if the result can't
+/// play anything.
+fn make_region(pzone: &Zone, izone: &Zone, headers: &[SampleHeader]) -> Option<Region> {
+    let header = headers.get(izone.get(GEN_SAMPLE_ID)? as u16 as usize)?;
+
+    // Key and velocity ranges are intersections of both levels.
+    let (pkey_lo, pkey_hi) = pzone.range(GEN_KEY_RANGE);
+    let (ikey_lo, ikey_hi) = izone.range(GEN_KEY_RANGE);
+    let (pvel_lo, pvel_hi) = pzone.range(GEN_VEL_RANGE);
+    let (ivel_lo, ivel_hi) = izone.range(GEN_VEL_RANGE);
+    let (key_lo, key_hi) = (pkey_lo.max(ikey_lo), pkey_hi.min(ikey_hi));
+    let (vel_lo, vel_hi) = (pvel_lo.max(ivel_lo), pvel_hi.min(ivel_hi));
+    if key_lo > key_hi || vel_lo > vel_hi {
+        return None;
+    }
+
+    // Sample addresses, with fine and coarse offsets
+    let offset = |fine: u16, coarse: u16| izone.get_or(fine, 0) as i64 + izone.get_or(coarse, 0) as i64 * 32768;
+    let start = header.start as i64 + offset(GEN_START_OFFSET, GEN_START_COARSE_OFFSET);
+    let end = header.end as i64 + offset(GEN_END_OFFSET, GEN_END_COARSE_OFFSET);
+    let loop_start = header.loop_start as i64 + offset(GEN_STARTLOOP_OFFSET, GEN_STARTLOOP_COARSE_OFFSET);
+    let loop_end = header.loop_end as i64 + offset(GEN_ENDLOOP_OFFSET, GEN_ENDLOOP_COARSE_OFFSET);
+    if start < 0 || end <= start {
+        return None;
+    }
+    let looping = izone.get_or(GEN_SAMPLE_MODES, 0) & 1 != 0 && loop_start >= start && loop_end > loop_start && loop_end <= end;
+
+    // Preset level tuning and attenuation are added on top of instrument level.
+    let root_key = match izone.get_or(GEN_OVERRIDING_ROOT_KEY, -1) {
+        key @ 0..=127 => key as i32,
+        _ => header.original_pitch as i32,
+    };
+    let tune = (izone.get_or(GEN_COARSE_TUNE, 0) + pzone.get_or(GEN_COARSE_TUNE, 0)) as f32
+        + (izone.get_or(GEN_FINE_TUNE, 0) + pzone.get_or(GEN_FINE_TUNE, 0)) as f32 / 100.
+        + header.pitch_correction as f32 / 100.;
+    let attenuation = izone.get_or(GEN_INITIAL_ATTENUATION, 0) as f32 + pzone.get_or(GEN_INITIAL_ATTENUATION, 0) as f32;
+
+    Some(Region {
+        key_lo,
+        key_hi,
+        vel_lo,
+        vel_hi,
+        start: start as usize,
+        end: end as usize,
+        loop_start: loop_start.max(0) as usize,
+        loop_end: loop_end.max(0) as usize,
+        looping,
+        sample_rate: header.sample_rate.max(1),
+        root_key,
+        tune,
+        gain: centibels_to_gain(attenuation),
+        attack: timecents_to_secs(izone.get_or(GEN_ATTACK_VOL_ENV, TIMECENTS_INSTANT)),
+        hold: timecents_to_secs(izone.get_or(GEN_HOLD_VOL_ENV, TIMECENTS_INSTANT)),
+        decay: timecents_to_secs(izone.get_or(GEN_DECAY_VOL_ENV, TIMECENTS_INSTANT)),
+        release: timecents_to_secs(izone.get_or(GEN_RELEASE_VOL_ENV, TIMECENTS_INSTANT)),
+        sustain: centibels_to_gain(izone.get_or(GEN_SUSTAIN_VOL_ENV, 0) as f32),
+    })
+}
+
+/// If the first zone lacks the terminal generator, it is a global zone. Returns the global zone
+/// (or an empty one) and the rest of the zones.
+fn split_global(zones: &[Zone], terminal_gen: u16) -> (Zone, &[Zone]) {
+    match zones.first() {
+        Some(first) if first.get(terminal_gen).is_none() => (*first, &zones[1..]),
+        _ => (Zone::default(), zones),
+    }
+}
+
+/// Read bag and generator chunks into zones.
+fn read_zones(bag: &[u8], gen: &[u8]) -> Vec<Zone> {
+    let gen_idx: Vec<usize> = bag.chunks_exact(4).map(|b| read_u16(b, 0) as usize).collect();
+    let gens: Vec<(u16, i16)> = gen.chunks_exact(4).map(|b| (read_u16(b, 0), read_u16(b, 2) as i16)).collect();
+    gen_idx
+        .windows(2)
+        .map(|pair| {
+            let mut zone = Zone::default();
+            for &(op, amount) in gens.get(pair[0]..pair[1]).unwrap_or_default() {
+                if let Some(slot) = zone.gens.get_mut(op as usize) {
+                    *slot = Some(amount);
+                }
+            }
+            zone
+        })
+        .collect()
+}
+
+/// Iterate over RIFF chunks: (id, data)
+fn riff_chunks(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id = [data[0], data[1], data[2], data[3]];
+        let size = read_u32(data, 4) as usize;
+        let Some(chunk) = data.get(8..8 + size) else {
+            break;
+        };
+        chunks.push((id, chunk));
+        // Chunks are padded to even size.
+        data = data.get(8 + size + (size & 1)..).unwrap_or_default();
+    }
+    chunks
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn timecents_to_secs(timecents: i16) -> f32 {
+    2f32.powf(timecents as f32 / 1200.)
+}
+
+fn centibels_to_gain(centibels: f32) -> f32 {
+    10f32.powf(-centibels.max(0.) / 200.)
+}
@@ -0,0 +1,273 @@
+//!
+//! Sample playback synthesizer for the MIDI device.
+//!
+//! Plays MIDI channel messages with a [SoundFont]. Supported messages:
+//! - Note on / off
+//! - Program change
+//! - Pitch bend (range: ±2 semitones)
+//! - Control change: bank select (0), volume (7), expression (11), all sound off (120),
+//!   reset all controllers (121), all notes off (123)
+//!
+//! Channel 10 (index 9) plays drums from bank 128, as in General MIDI.
+//!
+
+use super::soundfont::{Region, SoundFont};
+use crate::emulator::devices::dev_psg::{AudioChannel, SAMPLE_RATE};
+use std::sync::Arc;
+
+/// Oldest voice is stolen when this is exceeded.
+const MAX_VOICES: usize = 32;
+/// Output volume. Keeps a few overlapping notes from clipping.
+const MASTER_GAIN: f32 = 0.5;
+const DRUM_CHANNEL: usize = 9;
+const DRUM_BANK: u16 = 128;
+/// Voices quieter than this are dropped after release.
+const SILENCE: f32 = 0.0001;
+
+#[derive(Clone, Copy)]
+struct ChannelState {
+    bank: u16,
+    program: u16,
+    /// CC 7. Range: 0.0 to 1.0
+    volume: f32,
+    /// CC 11. Range: 0.0 to 1.0
+    expression: f32,
+    /// Pitch bend in semitones
+    bend: f32,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        ChannelState {
+            bank: 0,
+            program: 0,
+            volume: 100. / 127.,
+            expression: 1.,
+            bend: 0.,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum EnvStage {
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+}
+
+struct Voice {
+    channel: usize,
+    key: u8,
+    region: Region,
+    /// Position in sample data.
+    pos: f64,
+    /// Samples to advance per output sample, at zero pitch bend.
+    step: f64,
+    /// Gain from velocity and region attenuation
+    gain: f32,
+    env_stage: EnvStage,
+    env_level: f32,
+    /// Seconds spent in current stage.
+    env_time: f32,
+    /// Envelope level when release began.
+    release_level: f32,
+}
+
+impl Voice {
+    /// Advance envelope by one sample. Returns false when the voice is done.
+    fn update_envelope(&mut self) -> bool {
+        let dt = 1. / SAMPLE_RATE as f32;
+        self.env_time += dt;
+        let r = &self.region;
+        match self.env_stage {
+            EnvStage::Attack => {
+                self.env_level = (self.env_time / r.attack).min(1.);
+                if self.env_time >= r.attack {
+                    self.next_stage(EnvStage::Hold);
+                }
+            }
+            EnvStage::Hold => {
+                self.env_level = 1.;
+                if self.env_time >= r.hold {
+                    self.next_stage(EnvStage::Decay);
+                }
+            }
+            EnvStage::Decay => {
+                let t = (self.env_time / r.decay).min(1.);
+                self.env_level = 1. + (r.sustain - 1.) * t;
+                if t >= 1. {
+                    self.next_stage(EnvStage::Sustain);
+                }
+            }
+            EnvStage::Sustain => self.env_level = r.sustain,
+            EnvStage::Release => {
+                let t = (self.env_time / r.release).min(1.);
+                self.env_level = self.release_level * (1. - t);
+                if t >= 1. || self.env_level < SILENCE {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn next_stage(&mut self, stage: EnvStage) {
+        self.env_stage = stage;
+        self.env_time = 0.;
+    }
+
+    fn release(&mut self) {
+        if self.env_stage != EnvStage::Release {
+            self.release_level = self.env_level;
+            self.next_stage(EnvStage::Release);
+        }
+    }
+
+    /// Read the sample at current position, and advance. Returns None when the sample has ended.
+    fn next_sample(&mut self, samples: &[i16], bend: f32) -> Option<f32> {
+        let r = &self.region;
+        let idx = self.pos as usize;
+        if idx >= r.end || idx >= samples.len() {
+            return None;
+        }
+        let frac = (self.pos - idx as f64) as f32;
+        let next_idx = match r.looping && idx + 1 >= r.loop_end {
+            true => r.loop_start,
+            false => idx + 1,
+        };
+        let a = samples[idx] as f32;
+        let b = samples.get(next_idx).copied().unwrap_or(0) as f32;
+        let value = (a + (b - a) * frac) / 32768.;
+
+        self.pos += self.step * 2f64.powf(bend as f64 / 12.);
+        if r.looping && self.pos >= r.loop_end as f64 {
+            self.pos -= (r.loop_end - r.loop_start) as f64;
+        }
+        Some(value)
+    }
+}
+
+/// Synthesizer. Wrapped in `Arc<Mutex<_>>` like the PSG channels.
+pub(crate) struct MidiSynth {
+    soundfont: Arc<SoundFont>,
+    channels: [ChannelState; 16],
+    voices: Vec<Voice>,
+}
+
+impl MidiSynth {
+    pub fn new(soundfont: Arc<SoundFont>) -> Self {
+        let mut synth = MidiSynth {
+            soundfont,
+            channels: [ChannelState::default(); 16],
+            voices: Vec::new(),
+        };
+        synth.reset();
+        synth
+    }
+
+    /// Stop all sound and reset all channels.
+    pub fn reset(&mut self) {
+        self.voices.clear();
+        self.channels = [ChannelState::default(); 16];
+        self.channels[DRUM_CHANNEL].bank = DRUM_BANK;
+    }
+
+    /// Handle a complete channel message. `data` contains 1 or 2 data bytes.
+    pub fn message(&mut self, status: u8, data: &[u8]) {
+        let channel = (status & 0x0f) as usize;
+        let d0 = data.first().copied().unwrap_or(0);
+        let d1 = data.get(1).copied().unwrap_or(0);
+        match status & 0xf0 {
+            0x80 => self.note_off(channel, d0),
+            0x90 if d1 == 0 => self.note_off(channel, d0),
+            0x90 => self.note_on(channel, d0, d1),
+            0xb0 => self.control_change(channel, d0, d1),
+            0xc0 => self.channels[channel].program = d0 as u16,
+            0xe0 => {
+                let value = ((d1 as i32) << 7 | d0 as i32) - 0x2000;
+                self.channels[channel].bend = value as f32 / 0x2000 as f32 * 2.;
+            }
+            // Aftertouch is ignored.
+            _ => (),
+        }
+    }
+
+    fn note_on(&mut self, channel: usize, key: u8, vel: u8) {
+        let state = self.channels[channel];
+        let regions = self.soundfont.regions(state.bank, state.program, key, vel);
+        for region in regions {
+            if self.voices.len() >= MAX_VOICES {
+                self.voices.remove(0);
+            }
+            let pitch = key as f32 - region.root_key as f32 + region.tune;
+            let step = region.sample_rate as f64 / SAMPLE_RATE as f64 * 2f64.powf(pitch as f64 / 12.);
+            let vel_gain = (vel as f32 / 127.).powi(2);
+            self.voices.push(Voice {
+                channel,
+                key,
+                pos: region.start as f64,
+                step,
+                gain: vel_gain * region.gain,
+                env_stage: EnvStage::Attack,
+                env_level: 0.,
+                env_time: 0.,
+                release_level: 0.,
+                region,
+            });
+        }
+    }
+
+    fn note_off(&mut self, channel: usize, key: u8) {
+        for voice in self.voices.iter_mut().filter(|v| v.channel == channel && v.key == key) {
+            voice.release();
+        }
+    }
+
+    fn control_change(&mut self, channel: usize, controller: u8, value: u8) {
+        let state = &mut self.channels[channel];
+        match controller {
+            0 if channel != DRUM_CHANNEL => state.bank = value as u16,
+            7 => state.volume = value as f32 / 127.,
+            11 => state.expression = value as f32 / 127.,
+            120 => self.voices.retain(|v| v.channel != channel),
+            121 => {
+                state.volume = ChannelState::default().volume;
+                state.expression = 1.;
+                state.bend = 0.;
+            }
+            123 => {
+                for voice in self.voices.iter_mut().filter(|v| v.channel == channel) {
+                    voice.release();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Release all notes on all channels.
+    pub fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.release();
+        }
+    }
+}
+
+impl AudioChannel for MidiSynth {
+    fn get_next_sample(&mut self) -> f32 {
+        let samples = &self.soundfont.samples;
+        let channels = &self.channels;
+        let mut output = 0.;
+        self.voices.retain_mut(|voice| {
+            let state = channels[voice.channel];
+            let Some(value) = voice.next_sample(samples, state.bend) else {
+                return false;
+            };
+            let alive = voice.update_envelope();
+            output += value * voice.gain * voice.env_level * state.volume * state.expression;
+            alive
+        });
+        (output * MASTER_GAIN).clamp(-1., 1.)
+    }
+}
@@ -0,0 +1,154 @@
+//!
+//! Mouse
+//!
+//! Reports the pointer position over the Graphics Display, and mouse buttons. The GUI keeps the
+//! state up to date, and reading never blocks.
+//!
+//! Position is in display pixel coordinates: x 0-159, y 0-119, or x 0-319, y 0-239 in the 320x240
+//! display mode. When the pointer is not over the display, both are -1. While a button is held,
+//! the pointer is tracked outside the display too, clamped to the edges.
+//!
+//! Buttons:
+//! | Bit | Button |
+//! | --- | ------ |
+//! | 0   | Left   |
+//! | 1   | Right  |
+//! | 2   | Middle |
+//!
+//! Ports:
+//!  - Port 0: X (global port 0x58)
+//!  - Port 1: Y (global port 0x59)
+//!  - Port 2: Button state (global port 0x5a)
+//!  - Port 3: Button clicks (global port 0x5b)
+//!
+//! Read Behaviour:
+//! | Port | Effect                                                                  |
+//! | ---- | ----------------------------------------------------------------------- |
+//! | 0    | Returns pointer x                                                       |
+//! | 1    | Returns pointer y                                                       |
+//! | 2    | Returns currently held buttons                                          |
+//! | 3    | Returns buttons that were pressed since the last read, and clears them |
+//!
+//! Pressing a button raises the mouse interrupt (IVT entry 8), if it's enabled in the PIC.
+//!
+//! Writing to the mouse is an error.
+//!
+use super::{Device, PMIO};
+
+/// Only the 3 lowest bits are used.
+const BUTTON_MASK: i32 = 0b_111;
+
+/// Mouse
+pub(crate) struct DevMouse {
+    x: i32,
+    y: i32,
+    /// Buttons currently held down.
+    buttons: i32,
+    /// Buttons that have been pressed since port 3 was last read.
+    clicked: i32,
+    /// Set when a button is pressed. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl Default for DevMouse {
+    fn default() -> Self {
+        DevMouse {
+            x: -1,
+            y: -1,
+            buttons: 0,
+            clicked: 0,
+            interrupt: false,
+        }
+    }
+}
+
+impl DevMouse {
+    /// Update mouse state. Emulator calls this when GUI reports a change.
+    pub fn set_state(&mut self, x: i32, y: i32, buttons: i32) {
+        let buttons = buttons & BUTTON_MASK;
+        let pressed = buttons & !self.buttons;
+        if pressed != 0 {
+            self.clicked |= pressed;
+            self.interrupt = true;
+        }
+        self.x = x;
+        self.y = y;
+        self.buttons = buttons;
+    }
+}
+
+impl Device for DevMouse {
+    fn reset(&mut self) {
+        *self = DevMouse::default();
+    }
+    fn on(&mut self) {}
+    fn off(&mut self) {
+        self.clicked = 0;
+        self.interrupt = false;
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevMouse {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.x),
+            1 => Ok(self.y),
+            2 => Ok(self.buttons),
+            3 => Ok(std::mem::take(&mut self.clicked)),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, _port: u8, _value: i32) -> Result<(), ()> {
+        Err(()) // You can't write into the mouse!
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_mouse() -> Result<(), ()> {
+        let mut mouse = DevMouse::default();
+
+        // Test wrong usage
+        assert!(mouse.read_port(4).is_err());
+        assert!(mouse.write_port(0, 0).is_err());
+        assert!(mouse.write_port(2, 55).is_err());
+
+        // Not over the display
+        assert_eq!(mouse.read_port(0)?, -1);
+        assert_eq!(mouse.read_port(1)?, -1);
+        assert_eq!(mouse.read_port(2)?, 0);
+
+        // Move over the display
+        mouse.set_state(80, 60, 0);
+        assert_eq!(mouse.read_port(0)?, 80);
+        assert_eq!(mouse.read_port(1)?, 60);
+        assert!(!mouse.interrupt);
+
+        // Quick left click between polls is remembered once.
+        mouse.set_state(81, 60, 0b_001);
+        mouse.set_state(82, 61, 0);
+        assert!(mouse.interrupt);
+        assert_eq!(mouse.read_port(2)?, 0);
+        assert_eq!(mouse.read_port(3)?, 0b_001);
+        assert_eq!(mouse.read_port(3)?, 0);
+
+        // Drag with right button. Releasing doesn't interrupt.
+        mouse.interrupt = false;
+        mouse.set_state(82, 61, 0b_010);
+        assert!(mouse.interrupt);
+        mouse.interrupt = false;
+        mouse.set_state(100, 70, 0b_010);
+        mouse.set_state(100, 70, 0);
+        assert!(!mouse.interrupt);
+
+        // Out of range bits are ignored
+        mouse.set_state(0, 0, -1);
+        assert_eq!(mouse.read_port(2)?, 0b_111);
+
+        Ok(())
+    }
+}
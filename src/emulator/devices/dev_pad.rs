@@ -1,5 +1,115 @@
 //!
-//! This will become a gamepad port.
+//! Gamepad port.
 //!
-//use super::PMIO;
+//! Unlike =KBD, reading the gamepad never blocks. The GUI keeps the button state up to date, and
+//! the program can poll it whenever it wants.
+//!
+//! Buttons:
+//! | Bit | Button |
+//! | --- | ------ |
+//! | 0   | Up     |
+//! | 1   | Down   |
+//! | 2   | Left   |
+//! | 3   | Right  |
+//! | 4   | A      |
+//! | 5   | B      |
+//! | 6   | Select |
+//! | 7   | Start  |
+//!
+//! Ports:
+//!  - Port 0: Button state
+//!  - Port 1: Button presses
+//!
+//! Read Behaviour:
+//! | Port | Effect                                                                  |
+//! | ---- | ----------------------------------------------------------------------- |
+//! | 0    | Returns currently held buttons                                          |
+//! | 1    | Returns buttons that were pressed since the last read, and clears them |
+//!
+//! Port 1 makes sure short taps aren't missed between polls.
+//!
+//! Writing to the gamepad is an error.
+//!
+use super::{Device, PMIO};
+
+/// Only the 8 lowest bits are used.
+const BUTTON_MASK: i32 = 0xff;
+
+/// Gamepad port
+#[derive(Default)]
+pub(crate) struct DevPad {
+    /// Buttons currently held down.
+    buttons: i32,
+    /// Buttons that have been pressed since port 1 was last read.
+    pressed: i32,
+}
+
+impl DevPad {
+    /// Update button state. Emulator calls this when GUI reports a change.
+    pub fn set_buttons(&mut self, buttons: i32) {
+        let buttons = buttons & BUTTON_MASK;
+        self.pressed |= buttons & !self.buttons;
+        self.buttons = buttons;
+    }
+}
+
+impl Device for DevPad {
+    fn reset(&mut self) {
+        self.buttons = 0;
+        self.pressed = 0;
+    }
+    fn on(&mut self) {}
+    fn off(&mut self) {
+        self.pressed = 0;
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevPad {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.buttons),
+            1 => Ok(std::mem::take(&mut self.pressed)),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, _port: u8, _value: i32) -> Result<(), ()> {
+        Err(()) // You can't write into the gamepad!
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_pad() -> Result<(), ()> {
+        let mut pad = DevPad::default();
+
+        // Test wrong usage
+        assert!(pad.read_port(2).is_err());
+        assert!(pad.write_port(0, 0).is_err());
+        assert!(pad.write_port(1, 55).is_err());
+
+        // Nothing pressed
+        assert_eq!(pad.read_port(0)?, 0);
+        assert_eq!(pad.read_port(1)?, 0);
+
+        // Hold up + A
+        pad.set_buttons(0b_0001_0001);
+        assert_eq!(pad.read_port(0)?, 0b_0001_0001);
+
+        // Tap B between polls: it's gone from state, but the press is remembered once.
+        pad.set_buttons(0b_0011_0001);
+        pad.set_buttons(0b_0001_0001);
+        assert_eq!(pad.read_port(0)?, 0b_0001_0001);
+        assert_eq!(pad.read_port(1)?, 0b_0011_0001);
+        assert_eq!(pad.read_port(1)?, 0);
+
+        // Out of range bits are ignored
+        pad.set_buttons(-1);
+        assert_eq!(pad.read_port(0)?, 0xff);
 
+        Ok(())
+    }
+}
@@ -7,30 +7,33 @@
 //!
 //! The PIC allows devices to trigger interrupts. It also contains a simple builtin timer.
 //!
-//! ```
-//! The PIC contains two 8-bit registers:
+//! The PIC contains two 16-bit registers:
 //! - Mask Register: Which interrupts can fire
 //! - Flag Register: Currently firing interrupts
 //!
-//! Size of the registers may be increased later.
-//!
-//!
 //! Interrupt map (subject to change):
-//! | Bit | Device |
-//! | --- | ------ |
-//! | 0   |        |
-//! | 1   | Timer  |
-//! | 2   |        |
-//! | 3   |        |
-//! | 4   |        |
-//! | 5   |        |
-//! | 6   |        |
-//! | 7   |        |
+//! | Bit | Device   | IVT entry |
+//! | --- | -------- | --------- |
+//! | 0   |          |           |
+//! | 1   | Timer    | 6         |
+//! | 2   | Keyboard | 7         |
+//! | 3   | Mouse    | 8         |
+//! | 4   | Disc     | 9         |
+//! | 5   | Printer  | 10        |
+//! | 6   | UART     | 4         |
+//! | 7   | VBlank   | 4         |
+//! | 8   | Blitter  | 4         |
+//! | 9.. |          | 4         |
+//!
+//! Bits 1 to 5 have their own IVT entries. Bits above 5 all share IVT entry 4, so their handler
+//! has to read the Flag Register to find out which device is asking.
+//! If many interrupts are firing at once, the lowest bit goes first.
 //!
 //! Ports:
 //!  - Port 0: Command
 //!  - Port 1: Mask
 //!  - Port 2: Timer
+//!  - Port 3: Acknowledge
 //!
 //! Read Behaviour:
 //! | Port | Effect                |
@@ -47,19 +50,34 @@
 //! | 0    | 2     | Enable PIC (doesn't affect Mask or Flag registers)                          |
 //! | 1    | any   | Set Mask Register. If a bit is cleared, it is also cleared in Flag Register |
 //! | 2    | any   | Set Timer Reload Value. Resets timer.                                       |
+//! | 3    | any   | Clear the given bits from Flag Register                                     |
 //!
-//! So far this only accounts for timer. TODO: ther rest of it.
+//! An interrupt handler must clear its bit before `IEXIT`, or it will fire again right away.
 //!
 use super::{Device, PMIO};
 use std::time::Duration;
 
-const DEFAULT_MASK: u8 = 0b_00000010;
-const MASK_TIMER: u8 = 0b_00000010;
+const DEFAULT_MASK: u16 = 0b_00000010;
+pub(crate) const MASK_TIMER: u16 = 0b_00000010;
+pub(crate) const MASK_KBD: u16 = 0b_00000100;
+pub(crate) const MASK_MOUSE: u16 = 0b_00001000;
+pub(crate) const MASK_DISK: u16 = 0b_00010000;
+pub(crate) const MASK_PRINTER: u16 = 0b_00100000;
+pub(crate) const MASK_UART: u16 = 0b_01000000;
+pub(crate) const MASK_VBLANK: u16 = 0b_10000000;
+pub(crate) const MASK_BLITTER: u16 = 0b_00000001_00000000;
+
+/// IVT entry of the first bit.
+const IVT_FIRST: u32 = 5;
+/// Highest bit that has its own IVT entry.
+const IVT_LAST_BIT: u32 = 5;
+/// IVT entry for bits that don't have their own.
+const IVT_SHARED: i32 = 4;
 
 pub(crate) struct DevPIC {
     enabled: bool,
-    mask: u8,
-    pub(crate) flag: u8,
+    mask: u16,
+    pub(crate) flag: u16,
 
     timer: Duration,
     timer_reload: u32,
@@ -86,8 +104,11 @@ impl Device for DevPIC {
         self.timer_reload = 0;
         self.reset_timer();
     }
-    fn on(&mut self) {}
+    fn on(&mut self) {
+        self.reset();
+    }
     fn off(&mut self) {}
+    fn set_pause(&mut self, _paused: bool) {}
 }
 
 impl PMIO for DevPIC {
@@ -107,11 +128,15 @@ impl PMIO for DevPIC {
                 2 => self.enabled = true,
                 _ => (),
             },
-            1 => self.mask = value as u8,
+            1 => {
+                self.mask = value as u16;
+                self.flag &= self.mask;
+            }
             2 => {
                 self.timer_reload = value as u32;
                 self.reset_timer()
             }
+            3 => self.flag &= !(value as u16),
             _ => return Err(()),
         }
         Ok(())
@@ -140,14 +165,31 @@ impl DevPIC {
         (self.flag & self.mask) != 0 && self.enabled
     }
 
+    /// Devices call this to request an interrupt. Masked interrupts are ignored.
+    pub(crate) fn raise(&mut self, bits: u16) {
+        self.flag |= bits & self.mask;
+    }
+
+    /// IVT entry of the interrupt that should be handled next, if any.
+    pub(crate) fn firing_vector(&mut self) -> Option<i32> {
+        if !self.is_firing() {
+            return None;
+        }
+        let bit = (self.flag & self.mask).trailing_zeros();
+        match bit {
+            1..=IVT_LAST_BIT => Some((IVT_FIRST + bit) as i32),
+            _ => Some(IVT_SHARED),
+        }
+    }
+
     fn reset_timer(&mut self) {
         self.timer = Duration::from_millis(self.timer_reload as u64);
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
 
     /// PIC timer test.
     #[test]
@@ -178,4 +220,30 @@ mod tests {
         Ok(())
     }
 
+    /// PIC vector test. Tests which IVT entry each bit goes to, and acknowledging.
+    #[test]
+    fn test_dev_pic_vector() -> Result<(), ()> {
+        let mut pic = DevPIC::default();
+        pic.write_port(1, 0xffff)?;
+        assert_eq!(pic.firing_vector(), None);
+
+        pic.raise(MASK_KBD | MASK_TIMER);
+        assert_eq!(pic.firing_vector(), Some(6)); // Timer goes first
+        pic.write_port(3, MASK_TIMER as i32)?; // Ack timer
+        assert_eq!(pic.firing_vector(), Some(7)); // Keyboard
+        pic.write_port(3, MASK_KBD as i32)?;
+        assert_eq!(pic.firing_vector(), None);
+
+        pic.raise(0b_0100_0000); // Shared entry
+        assert_eq!(pic.firing_vector(), Some(4));
+        pic.write_port(0, 1)?; // Disable
+        assert_eq!(pic.firing_vector(), None);
+        pic.write_port(0, 2)?; // Enable
+        pic.write_port(1, 0)?; // Masking clears the flag
+        assert_eq!(pic.read_port(0)?, 0);
+
+        pic.raise(MASK_KBD); // Masked out
+        assert_eq!(pic.firing_vector(), None);
+        Ok(())
+    }
 }
\ No newline at end of file
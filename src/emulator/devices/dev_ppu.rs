@@ -0,0 +1,313 @@
+//!
+//! Tile and sprite video processor (PPU)
+//!
+//! PPU draws a scrollable tile background and hardware sprites on top of the framebuffer image, so
+//! programs don't have to draw every pixel themselves. It's located at 0x7000-0x7fff.
+//!
+//! Graphics are made of 8x8 pixel tiles with 16 colors. Color 0 is transparent: wherever both the
+//! background and the sprites are transparent, the framebuffer shows through.
+//!
+//! Memory map:
+//! | Address       | Size | Contents                                        |
+//! | ------------- | ---- | ----------------------------------------------- |
+//! | 0x000 - 0x00f | 16   | Registers                                       |
+//! | 0x010 - 0x01f | 16   | Palette                                         |
+//! | 0x020 - 0x05f | 64   | Sprite table: 16 sprites, 4 words each          |
+//! | 0x400 - 0x7ff | 1024 | Tilemap: 32x32 tile indices                     |
+//! | 0x800 - 0xfff | 2048 | Patterns: 256 tiles, 8 words each               |
+//!
+//! Everything can be read back. Unused addresses and registers are errors.
+//!
+//! Registers:
+//! | Address | Register | Description                                          |
+//! | ------- | -------- | ---------------------------------------------------- |
+//! | 0x000   | Control  | Bit 0: show background, bit 1: show sprites          |
+//! | 0x001   | Scroll X | Horizontal background scroll in pixels               |
+//! | 0x002   | Scroll Y | Vertical background scroll in pixels                 |
+//!
+//! ### Palette
+//! 16 colors, in the same format as framebuffer pixels. Color 0 is never drawn.
+//!
+//! ### Patterns
+//! Each tile is 8 words, one for each row from top to bottom. A row has 8 pixels, 4 bits each.
+//! Leftmost pixel is in the highest 4 bits. For example, `0x01234567` is a row of colors 0 to 7.
+//!
+//! ### Tilemap
+//! The background is a 32x32 tile map (256x256 pixels), of which the 160x120 screen shows a part.
+//! PPU always works in 160x120 pixels. In the 320x240 display mode each PPU pixel covers 2x2
+//! display pixels.
+//! Each word is a tile index (0-255) of a map cell, row by row. Scrolling wraps around the map.
+//!
+//! ### Sprites
+//! Sprites are single tiles that can be placed anywhere on the screen, also partially outside it.
+//! Sprite 0 is drawn on top.
+//!
+//! | Offset | Description                                                          |
+//! | ------ | -------------------------------------------------------------------- |
+//! | 0      | X: left edge                                                         |
+//! | 1      | Y: top edge                                                          |
+//! | 2      | Tile index (0-255)                                                   |
+//! | 3      | Flags: Bit 0: visible, bit 1: flip horizontal, bit 2: flip vertical  |
+//!
+//! Sprite `n` is at `0x7020 + n * 4`.
+//!
+use super::dev_display_classic::decode_color;
+use super::{Device, MMIO};
+use image::{Rgba, RgbaImage};
+
+const SCREEN_W: usize = 160;
+const SCREEN_H: usize = 120;
+const TILE_SIZE: usize = 8;
+const MAP_TILES: usize = 32;
+const MAP_SIZE: usize = MAP_TILES * TILE_SIZE;
+const SPRITE_COUNT: usize = 16;
+
+const ADDR_PALETTE: usize = 0x010;
+const ADDR_SPRITES: usize = 0x020;
+const ADDR_TILEMAP: usize = 0x400;
+const ADDR_PATTERNS: usize = 0x800;
+
+const CTRL_BG: i32 = 0b_01;
+const CTRL_SPRITES: i32 = 0b_10;
+
+const SPRITE_VISIBLE: i32 = 0b_001;
+const SPRITE_FLIP_H: i32 = 0b_010;
+const SPRITE_FLIP_V: i32 = 0b_100;
+
+/// Tile and sprite video processor
+pub(crate) struct DevPPU {
+    control: i32,
+    scroll_x: i32,
+    scroll_y: i32,
+    palette: [i32; 16],
+    /// x, y, tile, flags
+    sprites: [[i32; 4]; SPRITE_COUNT],
+    tilemap: Vec<i32>,
+    patterns: Vec<i32>,
+}
+
+impl Default for DevPPU {
+    fn default() -> Self {
+        DevPPU {
+            control: 0,
+            scroll_x: 0,
+            scroll_y: 0,
+            palette: [0; 16],
+            sprites: [[0; 4]; SPRITE_COUNT],
+            tilemap: vec![0; MAP_TILES * MAP_TILES],
+            patterns: vec![0; 256 * TILE_SIZE],
+        }
+    }
+}
+
+impl DevPPU {
+    /// Draw background and sprites on top of a frame. The frame is a multiple of 160x120.
+    pub(crate) fn render(&self, frame: &mut RgbaImage) {
+        if self.control & CTRL_BG != 0 {
+            self.render_bg(frame);
+        }
+        if self.control & CTRL_SPRITES != 0 {
+            // Lower sprites go on top, so they're drawn last.
+            for sprite in self.sprites.iter().rev() {
+                self.render_sprite(frame, sprite);
+            }
+        }
+    }
+
+    fn render_bg(&self, frame: &mut RgbaImage) {
+        for y in 0..SCREEN_H {
+            let map_y = (y as i32 + self.scroll_y).rem_euclid(MAP_SIZE as i32) as usize;
+            for x in 0..SCREEN_W {
+                let map_x = (x as i32 + self.scroll_x).rem_euclid(MAP_SIZE as i32) as usize;
+                let tile = self.tilemap[(map_y / TILE_SIZE) * MAP_TILES + map_x / TILE_SIZE];
+                let color = self.tile_pixel(tile, map_x % TILE_SIZE, map_y % TILE_SIZE);
+                if color != 0 {
+                    plot(frame, x, y, decode_color(self.palette[color]));
+                }
+            }
+        }
+    }
+
+    fn render_sprite(&self, frame: &mut RgbaImage, sprite: &[i32; 4]) {
+        let [sprite_x, sprite_y, tile, flags] = *sprite;
+        if flags & SPRITE_VISIBLE == 0 {
+            return;
+        }
+        for ty in 0..TILE_SIZE {
+            let Ok(y) = usize::try_from(sprite_y.saturating_add(ty as i32)) else {
+                continue;
+            };
+            if y >= SCREEN_H {
+                continue;
+            }
+            for tx in 0..TILE_SIZE {
+                let Ok(x) = usize::try_from(sprite_x.saturating_add(tx as i32)) else {
+                    continue;
+                };
+                if x >= SCREEN_W {
+                    continue;
+                }
+                let px = if flags & SPRITE_FLIP_H != 0 { TILE_SIZE - 1 - tx } else { tx };
+                let py = if flags & SPRITE_FLIP_V != 0 { TILE_SIZE - 1 - ty } else { ty };
+                let color = self.tile_pixel(tile, px, py);
+                if color != 0 {
+                    plot(frame, x, y, decode_color(self.palette[color]));
+                }
+            }
+        }
+    }
+
+    /// Palette index of a pixel in a tile pattern.
+    fn tile_pixel(&self, tile: i32, x: usize, y: usize) -> usize {
+        let row = self.patterns[(tile as usize & 0xff) * TILE_SIZE + y];
+        (row >> (28 - x * 4)) as usize & 0xf
+    }
+
+    /// Get a mutable reference to memory at an address.
+    fn mem(&mut self, addr: usize) -> Option<&mut i32> {
+        match addr {
+            0x000 => Some(&mut self.control),
+            0x001 => Some(&mut self.scroll_x),
+            0x002 => Some(&mut self.scroll_y),
+            ADDR_PALETTE..=0x01f => Some(&mut self.palette[addr - ADDR_PALETTE]),
+            ADDR_SPRITES..=0x05f => {
+                let idx = addr - ADDR_SPRITES;
+                Some(&mut self.sprites[idx / 4][idx % 4])
+            }
+            ADDR_TILEMAP..=0x7ff => Some(&mut self.tilemap[addr - ADDR_TILEMAP]),
+            ADDR_PATTERNS..=0xfff => Some(&mut self.patterns[addr - ADDR_PATTERNS]),
+            _ => None,
+        }
+    }
+}
+
+/// Set a PPU pixel, scaled to frame size.
+fn plot(frame: &mut RgbaImage, x: usize, y: usize, color: Rgba<u8>) {
+    let scale = (frame.width() as usize / SCREEN_W).max(1);
+    for fy in y * scale..(y + 1) * scale {
+        for fx in x * scale..(x + 1) * scale {
+            frame.put_pixel(fx as u32, fy as u32, color);
+        }
+    }
+}
+
+impl Device for DevPPU {
+    fn reset(&mut self) {
+        *self = DevPPU::default();
+    }
+    fn on(&mut self) {}
+    fn off(&mut self) {
+        *self = DevPPU::default();
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl MMIO for DevPPU {
+    fn read(&mut self, addr: usize) -> Result<i32, ()> {
+        self.mem(addr).map(|value| *value).ok_or(())
+    }
+    fn write(&mut self, addr: usize, value: i32) -> Result<(), ()> {
+        *self.mem(addr).ok_or(())? = value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    fn blank_frame() -> RgbaImage {
+        RgbaImage::from_pixel(SCREEN_W as u32, SCREEN_H as u32, BLACK)
+    }
+
+    #[test]
+    fn test_dev_ppu() -> Result<(), ()> {
+        let mut ppu = DevPPU::default();
+
+        // Test wrong usage
+        assert!(ppu.read(0x003).is_err());
+        assert!(ppu.write(0x060, 0).is_err());
+        assert!(ppu.write(0x3ff, 0).is_err());
+        assert!(ppu.read(0x1000).is_err());
+
+        // Readback
+        ppu.write(0x001, -5)?;
+        assert_eq!(ppu.read(0x001)?, -5);
+        ppu.write(0x001, 0)?;
+
+        // Palette: 1 is red, 2 is blue.
+        ppu.write(ADDR_PALETTE + 1, 0x0f00)?;
+        ppu.write(ADDR_PALETTE + 2, 0x000f)?;
+        let red = decode_color(0x0f00);
+        let blue = decode_color(0x000f);
+
+        // Tile 1: left column is red, top row is blue, rest is transparent.
+        ppu.write(ADDR_PATTERNS + 8, 0x22222222)?;
+        for row in 1..8 {
+            ppu.write(ADDR_PATTERNS + 8 + row, 0x10000000)?;
+        }
+        assert_eq!(ppu.read(ADDR_PATTERNS + 9)?, 0x10000000);
+
+        // Tile 1 at map cell (1, 0).
+        ppu.write(ADDR_TILEMAP + 1, 1)?;
+
+        // Nothing is shown while disabled.
+        let mut frame = blank_frame();
+        ppu.render(&mut frame);
+        assert!(frame.pixels().all(|px| *px == BLACK));
+
+        // Background
+        ppu.write(0x000, CTRL_BG)?;
+        let mut frame = blank_frame();
+        ppu.render(&mut frame);
+        assert_eq!(*frame.get_pixel(8, 0), blue);
+        assert_eq!(*frame.get_pixel(8, 1), red);
+        assert_eq!(*frame.get_pixel(9, 1), BLACK); // Transparent
+        assert_eq!(*frame.get_pixel(0, 0), BLACK); // Tile 0 is empty
+
+        // Scroll, and wrap around the map.
+        ppu.write(0x001, 4)?;
+        ppu.write(0x002, MAP_SIZE as i32 - 1)?;
+        let mut frame = blank_frame();
+        ppu.render(&mut frame);
+        assert_eq!(*frame.get_pixel(4, 1), blue);
+        assert_eq!(*frame.get_pixel(4, 2), red);
+        ppu.write(0x001, 0)?;
+        ppu.write(0x002, 0)?;
+
+        // Sprite 0 flipped, partially off screen. Sprite 1 is below it.
+        ppu.write(0x000, CTRL_SPRITES)?;
+        ppu.write(ADDR_SPRITES, -4)?;
+        ppu.write(ADDR_SPRITES + 1, 50)?;
+        ppu.write(ADDR_SPRITES + 2, 1)?;
+        ppu.write(ADDR_SPRITES + 3, SPRITE_VISIBLE | SPRITE_FLIP_H | SPRITE_FLIP_V)?;
+        ppu.write(ADDR_SPRITES + 4, 0)?;
+        ppu.write(ADDR_SPRITES + 5, 50)?;
+        ppu.write(ADDR_SPRITES + 6, 1)?;
+        ppu.write(ADDR_SPRITES + 7, SPRITE_VISIBLE)?;
+        let mut frame = blank_frame();
+        ppu.render(&mut frame);
+        // Sprite 0 bottom row is blue after the flip, and covers sprite 1.
+        assert_eq!(*frame.get_pixel(0, 57), blue);
+        assert_eq!(*frame.get_pixel(3, 57), blue);
+        // Sprite 0 right column is red, sprite 1 left column too.
+        assert_eq!(*frame.get_pixel(3, 52), red);
+        assert_eq!(*frame.get_pixel(0, 52), red);
+        assert_eq!(*frame.get_pixel(1, 52), BLACK);
+
+        // 320x240 frame: each pixel is doubled.
+        let mut frame = RgbaImage::from_pixel(320, 240, BLACK);
+        ppu.render(&mut frame);
+        assert_eq!(*frame.get_pixel(0, 104), red);
+        assert_eq!(*frame.get_pixel(1, 105), red);
+        assert_eq!(*frame.get_pixel(2, 104), BLACK);
+
+        // Off resets everything.
+        ppu.off();
+        assert_eq!(ppu.read(ADDR_PALETTE + 1)?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//!
+//! Line printer
+//!
+//! Prints text into the Printer panel, and optionally appends it into a host text file.
+//!
+//! The printer is slow: after each write it stays busy for a while. A program can either poll the
+//! status port, or wait for the printer interrupt (IVT entry 10), which is raised when the printer
+//! becomes ready again, if it's enabled in the PIC.
+//!
+//! Ports:
+//!  - Port 0: Status (global port 0x68)
+//!  - Port 1: Character (global port 0x69)
+//!  - Port 2: Integer (global port 0x6a)
+//!
+//! Status bits:
+//! | Bit | Meaning                                 |
+//! | --- | --------------------------------------- |
+//! | 0   | Busy: the printer can't take input now  |
+//!
+//! | Port | Read    | Write                                              |
+//! | ---- | ------- | -------------------------------------------------- |
+//! | 0    | Status  | Error                                              |
+//! | 1    | Error   | Print a character (Unicode code point)             |
+//! | 2    | Error   | Print an integer in decimal                        |
+//!
+//! Character 12 (form feed) ends the page.
+//!
+//! Anything written while busy is dropped and returns an error, and so does an invalid character.
+//! OUT ignores errors, so a program that doesn't check the status just loses the output.
+//!
+use super::{Device, PMIO};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// How long the printer stays busy after a write, in seconds of emulated time.
+const PRINT_TIME: f64 = 0.01;
+
+const STATUS_BUSY: i32 = 0b_1;
+
+/// Line printer
+#[derive(Default)]
+pub(crate) struct DevPrinter {
+    output_path: Option<PathBuf>,
+    /// Output file is kept open while the machine is on.
+    output_file: Option<File>,
+    /// Printed text that the GUI hasn't received yet.
+    pending: String,
+    /// Emulated time left until the printer is ready.
+    busy_time: Option<f64>,
+    /// Set when printer becomes ready. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl DevPrinter {
+    /// Set host file for printer output. Takes effect next time the machine is turned on.
+    pub fn set_output_path(&mut self, path: Option<PathBuf>) {
+        self.output_path = path;
+    }
+
+    /// Take text that has been printed since last call.
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Advance emulated time. Printer becomes ready when its time is up.
+    pub fn update(&mut self, delta_t: f64) {
+        let Some(time) = self.busy_time else {
+            return;
+        };
+        let time = time - delta_t;
+        if time > 0. {
+            self.busy_time = Some(time);
+            return;
+        }
+        self.busy_time = None;
+        self.interrupt = true;
+    }
+
+    fn print(&mut self, text: &str) -> Result<(), ()> {
+        if self.busy_time.is_some() {
+            return Err(());
+        }
+        self.pending += text;
+        if let Some(file) = &mut self.output_file {
+            let _ = file.write_all(text.as_bytes());
+        }
+        self.busy_time = Some(PRINT_TIME);
+        Ok(())
+    }
+
+    fn open_output(&mut self) {
+        self.output_file = None;
+        let Some(path) = &self.output_path else {
+            return;
+        };
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => self.output_file = Some(file),
+            Err(e) => println!("printer: couldn't open {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Device for DevPrinter {
+    fn reset(&mut self) {
+        self.output_file = None;
+        self.busy_time = None;
+        self.interrupt = false;
+    }
+    fn on(&mut self) {
+        self.busy_time = None;
+        self.interrupt = false;
+        self.open_output();
+    }
+    fn off(&mut self) {
+        self.output_file = None;
+        self.busy_time = None;
+        self.interrupt = false;
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevPrinter {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => match self.busy_time {
+                Some(_) => Ok(STATUS_BUSY),
+                None => Ok(0),
+            },
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        match port {
+            1 => {
+                let c = char::from_u32(value as u32).ok_or(())?;
+                self.print(c.encode_utf8(&mut [0; 4]))
+            }
+            2 => self.print(&value.to_string()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn test_dev_printer() -> Result<(), ()> {
+        let path = temp_dir().join("titomachine_test_dev_printer.txt");
+        let _ = fs::remove_file(&path);
+
+        let mut printer = DevPrinter::default();
+        printer.set_output_path(Some(path.clone()));
+        printer.on();
+
+        // Test wrong usage
+        assert!(printer.read_port(1).is_err());
+        assert!(printer.read_port(3).is_err());
+        assert!(printer.write_port(0, 0).is_err());
+        assert!(printer.write_port(1, -1).is_err());
+        assert!(printer.write_port(1, 0xd800).is_err()); // Surrogate isn't a char
+
+        // Print, and get dropped while busy.
+        assert_eq!(printer.read_port(0)?, 0);
+        printer.write_port(1, 'ä' as i32)?;
+        assert_eq!(printer.read_port(0)?, STATUS_BUSY);
+        assert!(printer.write_port(1, 'x' as i32).is_err());
+        printer.update(PRINT_TIME / 2.);
+        assert!(!printer.interrupt);
+        printer.update(PRINT_TIME / 2.);
+        assert!(printer.interrupt);
+        assert_eq!(printer.read_port(0)?, 0);
+
+        printer.write_port(2, -55)?;
+        printer.update(PRINT_TIME);
+        printer.write_port(1, '\n' as i32)?;
+        assert_eq!(printer.take_output(), "ä-55\n");
+        assert_eq!(printer.take_output(), "");
+
+        printer.off();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ä-55\n");
+
+        let _ = fs::remove_file(&path);
+        Ok(())
+    }
+}
@@ -3,198 +3,402 @@
 //!
 //! PSG is an audio device with similar capabilities to an '80s home console.
 //!
-//! It has four channels:
+//! It has five channels:
 //! - ch0: PulseChannel
 //! - ch1: PulseChannel
 //! - ch2: RampChannel
 //! - ch3: NoiseChannel
+//! - ch4: PcmChannel
 //!
-//! The channel structs implement the AudioChannel trait.
+//! The channel structs implement the AudioChannel trait, and are held together in a ChannelBank.
 //!
-//! They are wrapped in are wrapped in `Arc<Mutex<_>>>`.  
+//! Register writes are timestamped in emulated time, and a Sequencer applies them to the bank
+//! at the right sample. Music then plays the same whatever the host or emulation speed is.
+//!
+//! The sequencer that plays through the speakers is wrapped in `Arc<Mutex<_>>>`, and shared with
+//! the audio sink.  
 //! Example:
-//! `let ch0 = Arc::new(Mutex::new(PulseChannel::default()));`
+//! `let live = Arc::new(Mutex::new(Sequencer::default()));`
+//!
+//! A second copy of the channels is rendered in step with emulated time. Status registers and
+//! WAV recording use it, so they don't depend on the audio device or on how fast the emulator runs.
+//!
+//! ## Registers
+//!
+//! Channel n is at 0xn0 relative to PSG. Not all channels have every register.
+//!
+//! | Offset | R/W | Register                                                     | Reset |
+//! |--------|-----|--------------------------------------------------------------|-------|
+//! | 0x0    | R/W | Note (MIDI key) (ch0-ch3, 69 is 440 Hz)                      | 69    |
+//! |        |     | Note (ch4): plays the sample at the note's pitch             | 0     |
+//! | 0x1    | R/W | Volume 0-255                                                 | 0     |
+//! | 0x2    | R/W | Pulse width / duty cycle 0-255 (ch0, ch1, ch2)               | 128   |
+//! |        |     | Playback rate in samples per second (ch4)                    | 22050 |
+//! | 0x3    | R/W | Envelope flags                                               | 0     |
+//! | 0x4    | R/W | Envelope length in ms                                        | 1000  |
+//! | 0x5    | R   | Envelope position 0-255                                      |       |
+//! | 0x6    | R   | Status: bit 0 active, bit 1 gate open, bit 2 envelope done   |       |
+//! |        | W   | Gate: nonzero is key on, 0 is key off                        |       |
+//! | 0x7    | R/W | Sample start address (ch4)                                   | 0     |
+//! | 0x8    | R/W | Sample length in samples (ch4)                               | 0     |
+//! | 0x9    | R/W | Loop point in samples from start, negative for no loop (ch4) | -1    |
+//! | 0xa    | R/W | Trigger (ch4): nonzero loads the sample and plays, 0 stops   | 0     |
+//! | 0xb    | R/W | Pan: -128 left, 0 center, 128 right                          | 0     |
+//! | 0xc    | R/W | ADSR attack in ms                                            | 0     |
+//! | 0xd    | R/W | ADSR decay in ms                                             | 0     |
+//! | 0xe    | R/W | ADSR sustain level 0-255                                     | 255   |
+//! | 0xf    | R/W | ADSR release in ms                                           | 0     |
+//!
+//! Mixer registers are shared by all channels.
+//!
+//! | Offset | R/W | Register                                                     | Reset |
+//! |--------|-----|--------------------------------------------------------------|-------|
+//! | 0xf0   | R/W | Master volume 0-255                                          | 255   |
+//! | 0xf1   | R/W | Mute: bit n mutes channel n                                  | 0     |
+//! | 0xf2   | R/W | Solo: if nonzero, only channels with their bit set play      | 0     |
+//!
+//! ## Envelope
+//!
+//! | Bit | Flag                                                                     |
+//! |-----|--------------------------------------------------------------------------|
+//! | 0   | Envelope controls volume                                                 |
+//! | 1   | Envelope controls pulse width / duty cycle                               |
+//! | 2   | Envelope controls pitch (sweep)                                          |
+//! | 3   | Gate: mute when the envelope has finished                                |
+//! | 4   | Falling instead of rising                                                |
+//! | 5   | ADSR mode                                                                |
+//! | 6   | Loop                                                                     |
+//! | 7   | Mirrored loop: every other cycle is reversed                             |
+//!
+//! In ADSR mode, key on starts attack from the current level, and the envelope decays to sustain
+//! level and holds it. Key off starts release. Attack, decay and release times are from silent to
+//! full or back, so shorter moves take less time. Envelope position reads the level, and the
+//! envelope is done when release has finished. Writing other registers doesn't restart ADSR, so
+//! notes can change while held.
+//!
+//! ## PCM channel
+//!
+//! ch4 plays 8-bit signed samples, packed 4 per word with the first sample in the high byte.
+//! Triggering copies the samples from memory, so the memory can be reused while they play.
+//! Writing a note plays the loop, or the whole sample if it doesn't loop, once per period of the
+//! note. That makes a short looped sample a wavetable. Writing a rate plays at that speed instead.
+//!
+//! Reading a writable register returns the last value written to it, or its reset value if it
+//! hasn't been written since reset. Reset values are what the channels actually do after reset. The gate is the exception: its address
+//! reads status. Other addresses in the PSG window read as 0.
+//!
+//! ## WAV recording
+//!
+//! If a record path is set, the output is also rendered to a .wav file, in step with emulated time.
+//! Recording starts when the machine is turned on, and the file is written when it's turned off.
+//!
+//! The record path is set in the GUI settings. Without the GUI, a compiled program can be rendered
+//! straight to a file with `titomachine --render-wav PROGRAM.b91 OUT.wav [SECONDS] [CLOCK_HZ]`.
 //!
 
 use super::{Device, MMIO};
+use crate::emulator::emu_debug::{PsgChannelState, PsgState};
 use rodio::{OutputStream, Sink, Source};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+mod channel_bank;
 mod envelope;
+mod mixer;
 mod noise_channel;
 mod note_table;
+mod pcm_channel;
 mod pulse_channel;
 mod ramp_channel;
+mod sequencer;
+mod wav;
 
-use self::noise_channel::NoiseChannel;
+use self::envelope::flag_names;
+use self::mixer::CHANNELS;
 use self::note_table::key_freq;
-use self::pulse_channel::PulseChannel;
-use self::ramp_channel::RampChannel;
+use self::sequencer::Sequencer;
+use self::wav::WavRecorder;
 
-const SAMPLE_RATE: u32 = 22050;
+pub(crate) const SAMPLE_RATE: u32 = 22050;
+/// Output is stereo.
+const CHANNELS_OUT: u16 = 2;
+/// Size of the PSG window.
+const REG_COUNT: usize = 0x100;
+/// PCM channel registers
+pub(crate) const REG_PCM_START: usize = 0x47;
+pub(crate) const REG_PCM_LENGTH: usize = 0x48;
+pub(crate) const REG_PCM_TRIGGER: usize = 0x4a;
+/// Longest sample the PCM channel can play.
+pub(crate) const PCM_MAX_SAMPLES: usize = 0x10000;
+/// How many of the latest samples of each channel are kept for the GUI.
+const SCOPE_LEN: usize = 1024;
+/// Channel registers
+const REG_NOTE: usize = 0x00;
+const REG_VOL: usize = 0x01;
+const REG_PW: usize = 0x02;
+const REG_ENV_MASK: usize = 0x03;
+const REG_ENV_LENGTH: usize = 0x04;
+const REG_ENV_POSITION: usize = 0x05;
+const REG_STATUS: usize = 0x06;
+const REG_SUSTAIN: usize = 0x0e;
+const REG_PCM_RATE: usize = 0x42;
+const REG_PCM_LOOP: usize = 0x49;
+/// Mixer registers
+const REG_PAN: usize = 0x0b;
+const REG_MASTER: usize = 0xf0;
+const REG_MUTE: usize = 0xf1;
+const REG_SOLO: usize = 0xf2;
 
 /// Device struct.
 ///
 pub(crate) struct DevPSG {
-    paused: bool,
     #[allow(dead_code)] // Output stream is never "used", but we have to keep it around in order to
     // get sound.
     stream: Option<OutputStream>,
-    sink0: Sink,
-    sink1: Sink,
-    sink2: Sink,
-    sink3: Sink,
-    ch0: Arc<Mutex<PulseChannel>>,
-    ch1: Arc<Mutex<PulseChannel>>,
-    ch2: Arc<Mutex<RampChannel>>,
-    ch3: Arc<Mutex<NoiseChannel>>,
+    sink: Sink,
+    live: Arc<Mutex<Sequencer>>,
+    /// Channels rendered in emulated time.
+    emu: Sequencer,
+    /// Last values written to registers.
+    regs: [i32; REG_COUNT],
+    /// Latest output of each channel in emulated time, for the GUI.
+    scope: Vec<VecDeque<f32>>,
+    /// Something has changed since the GUI last got the state.
+    state_changed: bool,
+    /// Channels muted from the GUI. Only affects live output.
+    host_mute: i32,
+    /// Channels soloed from the GUI. Only affects live output.
+    host_solo: i32,
+    /// Emulated time since turning on, in seconds.
+    time: f64,
+    /// Host file for WAV recording.
+    record_path: Option<PathBuf>,
+    recording: Option<Recording>,
+}
+
+/// WAV recording in progress.
+struct Recording {
+    path: PathBuf,
+    wav: WavRecorder,
 }
 
 impl Default for DevPSG {
     fn default() -> Self {
-        //
-        let try_default = OutputStream::try_default();
-
-        let sink0;
-        let sink1;
-        let sink2;
-        let sink3;
-
-        let stream;
-        // Host has an audio device, create sinks.
-        if try_default.is_ok() {
-            let (_stream, _stream_handle) = try_default.unwrap();
-            stream = Some(_stream);
-            sink0 = Sink::try_new(&_stream_handle).unwrap();
-            sink1 = Sink::try_new(&_stream_handle).unwrap();
-            sink2 = Sink::try_new(&_stream_handle).unwrap();
-            sink3 = Sink::try_new(&_stream_handle).unwrap();
+        let (stream, sink) = match OutputStream::try_default() {
+            // Host has an audio device, create sink.
+            Ok((stream, stream_handle)) => (Some(stream), Sink::try_new(&stream_handle).unwrap()),
+            // No audio devices available. Create a sink that does nothing.
+            Err(_) => (None, Sink::new_idle().0),
+        };
+        sink.pause();
+        let live = Arc::new(Mutex::new(Sequencer::live()));
+        sink.append(StereoSource::new(live.clone()));
+
+        DevPSG {
+            stream,
+            sink,
+            live,
+            emu: Sequencer::default(),
+            regs: reset_regs(),
+            scope: vec![VecDeque::from(vec![0.; SCOPE_LEN]); CHANNELS],
+            state_changed: true,
+            host_mute: 0,
+            host_solo: 0,
+            time: 0.,
+            record_path: None,
+            recording: None,
         }
-        // No audio devices available. Create sinks that do nothing.
-        else {
-            stream = None;
-            (sink0, _) = Sink::new_idle();
-            (sink1, _) = Sink::new_idle();
-            (sink2, _) = Sink::new_idle();
-            (sink3, _) = Sink::new_idle();
+    }
+}
+
+impl DevPSG {
+    /// Set host file for WAV recording. Takes effect next time the machine is turned on.
+    pub fn set_record_path(&mut self, path: Option<PathBuf>) {
+        self.record_path = path;
+    }
+
+    /// Mute and solo channels in live output. Bit n is channel n.
+    pub fn set_host_mixer(&mut self, mute: i32, solo: i32) {
+        self.host_mute = mute;
+        self.host_solo = solo;
+        let mixer = &mut self.live.lock().unwrap().bank.mixer;
+        mixer.host_mute = mute;
+        mixer.host_solo = solo;
+    }
+
+    /// State for the GUI, if it has changed.
+    pub fn take_state(&mut self) -> Option<PsgState> {
+        if !std::mem::take(&mut self.state_changed) {
+            return None;
         }
+        let bank = self.emu.current();
+        let channels = (0..CHANNELS)
+            .map(|ch| {
+                let base = ch << 4;
+                PsgChannelState {
+                    note: self.regs[base + REG_NOTE],
+                    freq: key_freq(self.regs[base + REG_NOTE]),
+                    vol: self.regs[base + REG_VOL],
+                    pw: self.regs[base + REG_PW],
+                    env_flags: flag_names(self.regs[base + REG_ENV_MASK]),
+                    env_stage: bank.env(ch).and_then(|env| env.get_stage()).map(|stage| stage.name()),
+                    env_position: bank.read_status(base + REG_ENV_POSITION).unwrap_or(0),
+                    status: bank.read_status(base + REG_STATUS).unwrap_or(0),
+                    waveform: self.scope[ch].iter().copied().collect(),
+                }
+            })
+            .collect();
+        Some(PsgState {
+            pan: (0..CHANNELS).map(|ch| self.regs[REG_PAN + (ch << 4)]).collect(),
+            master: self.regs[REG_MASTER],
+            mute: self.regs[REG_MUTE],
+            solo: self.regs[REG_SOLO],
+            channels,
+        })
+    }
 
-        sink0.pause();
-        sink1.pause();
-        sink2.pause();
-        sink3.pause();
+    /// Current emulated time in samples.
+    fn now(&self) -> u64 {
+        (self.time * SAMPLE_RATE as f64) as u64
+    }
+
+    /// Advance emulated time. Lets live output play up to it, and renders emulated channels.
+    pub fn update(&mut self, delta_t: f64) {
+        let prev = self.now();
+        self.time += delta_t;
+        let now = self.now();
+        if now == prev {
+            return;
+        }
+        self.state_changed = true;
+        self.live.lock().unwrap().now = Some(now);
+        while self.emu.position < now {
+            let frame = self.emu.get_next_frame();
+            if let Some(rec) = &mut self.recording {
+                rec.wav.push(frame);
+            }
+            for (scope, sample) in self.scope.iter_mut().zip(self.emu.bank.outputs) {
+                scope.pop_front();
+                scope.push_back(sample);
+            }
+        }
+    }
 
-        // Create channels
-        let ch0 = Arc::new(Mutex::new(PulseChannel::default()));
-        let ch1 = Arc::new(Mutex::new(PulseChannel::default()));
-        let ch2 = Arc::new(Mutex::new(RampChannel::default()));
-        let ch3 = Arc::new(Mutex::new(NoiseChannel::default()));
+    /// Where the PCM channel's samples are in memory: start address, and number of words.
+    pub fn pcm_region(&self) -> Result<(u32, u32), ()> {
+        let start = u32::try_from(self.regs[REG_PCM_START]).map_err(|_| ())?;
+        let length = usize::try_from(self.regs[REG_PCM_LENGTH]).map_err(|_| ())?;
+        if length > PCM_MAX_SAMPLES {
+            return Err(());
+        }
+        Ok((start, length.div_ceil(4) as u32))
+    }
 
-        // Create sources
-        sink0.append(AudioSource::new(ch0.clone()));
-        sink1.append(AudioSource::new(ch1.clone()));
-        sink2.append(AudioSource::new(ch2.clone()));
-        sink3.append(AudioSource::new(ch3.clone()));
+    /// Give the PCM channel new samples. `words` holds 4 samples each, and extra samples in the
+    /// last word are dropped.
+    pub fn load_pcm(&mut self, words: &[i32]) {
+        let mut samples: Vec<i8> = words.iter().flat_map(|word| word.to_be_bytes()).map(|b| b as i8).collect();
+        samples.truncate(self.regs[REG_PCM_LENGTH].max(0) as usize);
+        let samples: Arc<[i8]> = samples.into();
+        let now = self.now();
+        self.emu.push_samples(now, samples.clone());
+        self.live.lock().unwrap().push_samples(now, samples);
+    }
 
-        DevPSG {
-            paused: false,
-            stream,
-            sink0,
-            sink1,
-            sink2,
-            sink3,
-            ch0,
-            ch1,
-            ch2,
-            ch3,
+    fn finish_recording(&mut self) {
+        if let Some(rec) = self.recording.take() {
+            if let Err(e) = fs::write(&rec.path, rec.wav.to_bytes()) {
+                println!("psg: couldn't write {}: {}", rec.path.display(), e);
+            }
         }
     }
 }
 
 impl Device for DevPSG {
     fn reset(&mut self) {
-        self.sink0.clear();
-        self.sink1.clear();
-        self.sink2.clear();
-        self.sink3.clear();
-        self.ch0 = Arc::new(Mutex::new(PulseChannel::default()));
-        self.ch1 = Arc::new(Mutex::new(PulseChannel::default()));
-        self.ch2 = Arc::new(Mutex::new(RampChannel::default()));
-        self.ch3 = Arc::new(Mutex::new(NoiseChannel::default()));
-        self.sink0.append(AudioSource::new(self.ch0.clone()));
-        self.sink1.append(AudioSource::new(self.ch1.clone()));
-        self.sink2.append(AudioSource::new(self.ch2.clone()));
-        self.sink3.append(AudioSource::new(self.ch3.clone()));
+        *self.live.lock().unwrap() = Sequencer::live();
+        self.set_host_mixer(self.host_mute, self.host_solo);
+        self.emu = Sequencer::default();
+        self.regs = reset_regs();
+        self.scope = vec![VecDeque::from(vec![0.; SCOPE_LEN]); CHANNELS];
+        self.state_changed = true;
+        self.time = 0.;
+        self.recording = None;
     }
 
     fn on(&mut self) {
-        self.sink0.play();
-        self.sink1.play();
-        self.sink2.play();
-        self.sink3.play();
+        self.time = 0.;
+        let mut live = self.live.lock().unwrap();
+        live.restart();
+        self.emu = live.offline();
+        drop(live);
+        self.recording = self.record_path.clone().map(|path| Recording {
+            path,
+            wav: WavRecorder::default(),
+        });
+        self.sink.play();
     }
 
     fn off(&mut self) {
-        self.sink0.pause();
-        self.sink1.pause();
-        self.sink2.pause();
-        self.sink3.pause();
+        self.sink.pause();
+        self.finish_recording();
     }
 
     fn set_pause(&mut self, paused: bool) {
         if paused {
-            self.sink0.pause();
-            self.sink1.pause();
-            self.sink2.pause();
-            self.sink3.pause();
+            self.sink.pause();
         } else {
-            self.sink0.play();
-            self.sink1.play();
-            self.sink2.play();
-            self.sink3.play();
+            self.sink.play();
         }
     }
 }
 
 impl MMIO for DevPSG {
-    fn read(&mut self, _addr: usize) -> Result<i32, ()> {
-        Err(())
+    fn read(&mut self, addr: usize) -> Result<i32, ()> {
+        let bank = self.emu.current();
+        if let Ok(value) = bank.read_status(addr) {
+            return Ok(value);
+        }
+        match bank.check(addr) {
+            Ok(_) => Ok(self.regs[addr]),
+            Err(_) if addr < REG_COUNT => Ok(0),
+            Err(_) => Err(()),
+        }
     }
 
     fn write(&mut self, addr: usize, value: i32) -> Result<(), ()> {
-        //
-        match addr {
-            // ch0
-            0x00 => self.ch0.lock().unwrap().set_freq(key_freq(value)),
-            0x01 => self.ch0.lock().unwrap().set_vol(value),
-            0x02 => self.ch0.lock().unwrap().set_pw(value),
-            0x03 => self.ch0.lock().unwrap().set_env_mask(value),
-            0x04 => self.ch0.lock().unwrap().set_env_length(value),
-            // ch1
-            0x10 => self.ch1.lock().unwrap().set_freq(key_freq(value)),
-            0x11 => self.ch1.lock().unwrap().set_vol(value),
-            0x12 => self.ch1.lock().unwrap().set_pw(value),
-            0x13 => self.ch1.lock().unwrap().set_env_mask(value),
-            0x14 => self.ch1.lock().unwrap().set_env_length(value),
-            // ch2
-            0x20 => self.ch2.lock().unwrap().set_freq(key_freq(value)),
-            0x21 => self.ch2.lock().unwrap().set_vol(value),
-            0x22 => self.ch2.lock().unwrap().set_dc(value),
-            0x23 => self.ch2.lock().unwrap().set_env_mask(value),
-            0x24 => self.ch2.lock().unwrap().set_env_length(value),
-            // ch3
-            0x30 => self.ch3.lock().unwrap().set_freq(key_freq(value)),
-            0x31 => self.ch3.lock().unwrap().set_vol(value),
-            //0x32 =>
-            0x33 => self.ch3.lock().unwrap().set_env_mask(value),
-            0x34 => self.ch3.lock().unwrap().set_env_length(value),
-
-            _ => return Err(()),
-        }
+        let now = self.now();
+        self.emu.push(now, addr, value)?;
+        self.live.lock().unwrap().push(now, addr, value)?;
+        self.regs[addr] = value;
+        self.state_changed = true;
         Ok(())
     }
 }
 
+/// Register values after reset.
+fn reset_regs() -> [i32; REG_COUNT] {
+    let mut regs = [0; REG_COUNT];
+    for ch in 0..CHANNELS {
+        let base = ch << 4;
+        regs[base + REG_ENV_LENGTH] = 1000;
+        regs[base + REG_SUSTAIN] = 255;
+    }
+    // Tone channels play 440 Hz at 50% width.
+    for ch in 0..4 {
+        regs[(ch << 4) + REG_NOTE] = 69;
+    }
+    for ch in 0..3 {
+        regs[(ch << 4) + REG_PW] = 128;
+    }
+    regs[REG_PCM_RATE] = SAMPLE_RATE as i32;
+    regs[REG_PCM_LOOP] = -1;
+    regs[REG_MASTER] = 255;
+    regs
+}
+
 /// Any audio generator needs to implement this trait.
 pub trait AudioChannel {
     /// This is called SAMPLE_RATE times a second.
@@ -227,6 +431,47 @@ impl<T: AudioChannel> Iterator for AudioSource<T> {
     }
 }
 
+/// Stereo output of a sequencer. This is passed to the audio sink.
+pub(crate) struct StereoSource {
+    seq: Arc<Mutex<Sequencer>>,
+    /// Right sample of the current frame, if it hasn't been given out yet.
+    right: Option<f32>,
+}
+
+impl StereoSource {
+    pub(crate) fn new(seq: Arc<Mutex<Sequencer>>) -> Self {
+        Self { seq, right: None }
+    }
+}
+
+impl Iterator for StereoSource {
+    type Item = f32;
+    // Samples are interleaved: left, right, left, right...
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        let [left, right] = self.seq.lock().unwrap().get_next_frame();
+        self.right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for StereoSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        CHANNELS_OUT
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
 impl<T: AudioChannel> Source for AudioSource<T> {
     fn current_frame_len(&self) -> Option<usize> {
         None
@@ -242,25 +487,175 @@ impl<T: AudioChannel> Source for AudioSource<T> {
     }
 }
 
-/*
-// For waveform debugging
-fn oscilloscope(sample: f32) {
-    let mut display_sample = sample;
 
-    display_sample += 1.;
-    display_sample /= 2.;
+#[cfg(test)]
+mod test {
+    use super::channel_bank::{STATUS_ACTIVE, STATUS_ENV_DONE, STATUS_GATE};
+    use super::sequencer::{LATENCY, MAX_LAG};
+    use super::*;
+    use std::env::temp_dir;
 
-    if display_sample < 0. {
-        display_sample = 0.;
-    }
-    if display_sample > 1. {
-        display_sample = 1.;
-    }
+    #[test]
+    fn test_dev_psg() -> Result<(), ()> {
+        let path = temp_dir().join("titomachine_test_dev_psg.wav");
+        let _ = fs::remove_file(&path);
+
+        let mut psg = DevPSG::default();
+
+        // Test wrong usage
+        assert!(psg.write(0x05, 0).is_err());
+        assert!(psg.write(0x32, 0).is_err());
+        assert!(psg.write(0x45, 0).is_err());
+        assert!(psg.write(0x50, 0).is_err());
+        assert!(psg.read(0x100).is_err());
+        psg.write(REG_PCM_LENGTH, PCM_MAX_SAMPLES as i32 + 1)?;
+        assert!(psg.pcm_region().is_err());
+
+        // Readback, and unused addresses read as 0.
+        psg.write(0x14, 500)?;
+        assert_eq!(psg.read(0x14)?, 500);
+        assert_eq!(psg.read(0x32)?, 0);
+        assert_eq!(psg.read(0xff)?, 0);
+        psg.reset();
+        assert_eq!(psg.read(0x14)?, 1000);
+        assert_eq!(psg.read(0x10)?, 69);
+        assert_eq!(psg.read(0x12)?, 128);
+        assert_eq!(psg.read(REG_PCM_RATE)?, SAMPLE_RATE as i32);
+        assert_eq!(psg.read(REG_PCM_LOOP)?, -1);
+        assert_eq!(psg.read(0x1e)?, 255);
+        assert_eq!(psg.read(REG_MASTER)?, 255);
+
+        // State is sent to the GUI when something changes.
+        assert!(psg.take_state().is_some());
+        assert!(psg.take_state().is_none());
+        psg.write(0x2b, -64)?;
+        psg.write(0x13, 0b1001)?;
+        let state = psg.take_state().unwrap();
+        assert_eq!(state.pan, vec![0, 0, -64, 0, 0]);
+        assert_eq!(state.channels[1].env_flags, vec!["VOL", "GATE"]);
+        psg.reset();
+
+        // Turning off without a recording doesn't create a file.
+        psg.on();
+        psg.off();
+        assert!(!path.exists());
+
+        // Silence, then a tone on ch0 panned left, rendered in emulated time. Host mute doesn't
+        // affect the recording.
+        psg.set_record_path(Some(path.clone()));
+        psg.set_host_mixer(0b11111, 0);
+        psg.on();
+        psg.write(0x0b, -128)?;
+        psg.update(0.05);
+        psg.write(0x00, 69)?;
+        psg.write(0x01, 0xff)?;
+        for _ in 0..50 {
+            psg.update(0.001);
+        }
+        psg.off();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[22..24], &2u16.to_le_bytes());
+        assert_eq!(&bytes[24..28], &SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        let samples: Vec<i16> = bytes[44..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(&bytes[40..44], &(samples.len() as u32 * 2).to_le_bytes());
+        let left: Vec<i16> = samples.iter().step_by(2).copied().collect();
+        let right: Vec<i16> = samples.iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(left.len(), SAMPLE_RATE as usize / 10);
+        assert!(left[..1100].iter().all(|&s| s == 0));
+        assert!(left[1103..].iter().any(|&s| s.abs() > 1000));
+        assert!(right.iter().all(|&s| s == 0));
+        let waveform = &psg.take_state().unwrap().channels[0].waveform;
+        assert_eq!(waveform.len(), SCOPE_LEN);
+        assert!(waveform.iter().all(|s| s.abs() > 0.9));
+        psg.set_host_mixer(0, 0);
+
+        let _ = fs::remove_file(&path);
+
+        // Status follows emulated time: 100 ms envelope on a playing channel.
+        psg.on();
+        assert_eq!(psg.read(0x16)?, STATUS_GATE);
+        psg.write(0x11, 0xff)?;
+        psg.write(0x13, 8)?;
+        psg.write(0x14, 100)?;
+        assert_eq!(psg.read(0x15)?, 0);
+        psg.update(0.05);
+        assert!((120..=135).contains(&psg.read(0x15)?));
+        assert_eq!(psg.read(0x16)?, STATUS_ACTIVE | STATUS_GATE);
+        psg.update(0.06);
+        assert_eq!(psg.read(0x15)?, 255);
+        assert_eq!(psg.read(0x16)?, STATUS_ENV_DONE);
+        psg.off();
+
+        // ADSR: 10 ms attack, 10 ms decay to half, 20 ms release. Changing the note doesn't
+        // restart it, and it's silent once released.
+        psg.on();
+        for (addr, value) in [(0x31, 0xff), (0x33, 0b101001), (0x3c, 10), (0x3d, 10), (0x3e, 128), (0x3f, 20)] {
+            psg.write(addr, value)?;
+        }
+        assert_eq!(psg.read(0x3e)?, 128);
+        assert_eq!(psg.read(0x36)?, STATUS_ENV_DONE);
+        psg.write(0x36, 1)?;
+        assert_eq!(psg.read(0x36)?, STATUS_GATE);
+        psg.update(0.005);
+        assert!((120..=135).contains(&psg.read(0x35)?));
+        assert_eq!(psg.read(0x36)?, STATUS_ACTIVE | STATUS_GATE);
+        psg.update(0.02);
+        psg.write(0x30, 60)?;
+        psg.update(0.1);
+        assert_eq!(psg.read(0x35)?, 128);
+        assert_eq!(psg.read(0x36)?, STATUS_ACTIVE | STATUS_GATE);
+        psg.write(0x36, 0)?;
+        psg.update(0.005);
+        assert!((60..=70).contains(&psg.read(0x35)?));
+        psg.update(0.01);
+        assert_eq!(psg.read(0x35)?, 0);
+        assert_eq!(psg.read(0x36)?, STATUS_ENV_DONE);
+        assert_eq!(psg.take_state().unwrap().channels[3].env_stage, Some("off"));
+        psg.off();
 
-    let w = 100 - 1;
-    let l = (w as f32 * display_sample) as usize;
-    let r = w - l;
+        // Looping wavetable keeps playing.
+        psg.on();
+        psg.write(0x41, 0xff)?;
+        psg.write(0x40, 69)?;
+        psg.write(REG_PCM_START, 0x100)?;
+        psg.write(REG_PCM_LENGTH, 7)?;
+        psg.write(0x49, 0)?;
+        assert_eq!(psg.pcm_region(), Ok((0x100, 2)));
+        psg.load_pcm(&[0x00407f40, 0x00c081c0]);
+        psg.write(REG_PCM_TRIGGER, 1)?;
+        psg.update(0.1);
+        assert_eq!(psg.read(0x46)? & STATUS_ACTIVE, STATUS_ACTIVE);
+        psg.write(REG_PCM_TRIGGER, 0)?;
+        assert_eq!(psg.read(0x46)? & STATUS_ACTIVE, 0);
+        psg.off();
 
-    println!("{}#{}    {}", ".".repeat(l), ".".repeat(r), sample);
+        // Live output waits for emulation...
+        let mut seq = Sequencer::live();
+        assert!(seq.push(0, 0x05, 0).is_err());
+        seq.get_next_frame();
+        assert_eq!(seq.position, 0);
+        seq.now = Some(LATENCY + 10);
+        for _ in 0..20 {
+            seq.get_next_frame();
+        }
+        assert_eq!(seq.position, 10);
+        // ...and skips ahead when it's too far behind.
+        let now = SAMPLE_RATE as u64 * 10;
+        seq.now = Some(now);
+        seq.get_next_frame();
+        assert_eq!(seq.position, now - MAX_LAG + 1);
+
+        // Without anything pulling samples, the queue doesn't grow past MAX_LAG.
+        let mut seq = Sequencer::live();
+        for time in 0..SAMPLE_RATE as u64 * 10 {
+            seq.now = Some(time);
+            seq.push(time, 0x00, 60)?;
+        }
+        assert!(seq.queued() <= MAX_LAG as usize + 1);
+        Ok(())
+    }
 }
-*/
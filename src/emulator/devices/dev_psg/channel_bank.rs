@@ -0,0 +1,177 @@
+//!
+//! All PSG channels together
+//!
+//! The bank holds the state of every channel, applies register writes to them, and mixes them into
+//! stereo output. The live audio output and offline rendering each have their own bank, so they
+//! don't take samples from each other.
+//!
+
+use super::envelope::Envelope;
+use super::mixer::{Mixer, CHANNELS};
+use super::noise_channel::NoiseChannel;
+use super::note_table::key_freq;
+use super::pcm_channel::PcmChannel;
+use super::pulse_channel::PulseChannel;
+use super::ramp_channel::RampChannel;
+use super::AudioChannel;
+use crate::emulator::emu_debug::PSG_STATUS_ACTIVE;
+use std::sync::Arc;
+
+/// Status bit: channel is making sound.
+pub(crate) const STATUS_ACTIVE: i32 = PSG_STATUS_ACTIVE;
+/// Status bit: envelope gate is open.
+pub(crate) const STATUS_GATE: i32 = 2;
+/// Status bit: envelope has run out.
+pub(crate) const STATUS_ENV_DONE: i32 = 4;
+
+/// All PSG channels together
+#[derive(Clone, Default)]
+pub(crate) struct ChannelBank {
+    ch0: PulseChannel,
+    ch1: PulseChannel,
+    ch2: RampChannel,
+    ch3: NoiseChannel,
+    ch4: PcmChannel,
+    pub mixer: Mixer,
+    /// Latest sample of each channel, before mixing.
+    pub outputs: [f32; CHANNELS],
+}
+
+impl ChannelBank {
+    /// Check that a register exists, without writing it.
+    pub fn check(&self, addr: usize) -> Result<(), ()> {
+        match (addr >> 4, addr & 0xf) {
+            (0..=4, 0x0 | 0x1 | 0x3 | 0x4 | 0x6 | 0xb..=0xf) => Ok(()),
+            (0..=2 | 4, 0x2) => Ok(()),
+            (4, 0x7..=0xa) => Ok(()),
+            (0xf, 0x0..=0x2) => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// Envelope of a channel
+    pub fn env(&self, ch: usize) -> Option<&Envelope> {
+        match ch {
+            0 => Some(&self.ch0.env),
+            1 => Some(&self.ch1.env),
+            2 => Some(&self.ch2.env),
+            3 => Some(&self.ch3.env),
+            4 => Some(&self.ch4.env),
+            _ => None,
+        }
+    }
+
+    fn env_mut(&mut self, ch: usize) -> Option<&mut Envelope> {
+        match ch {
+            0 => Some(&mut self.ch0.env),
+            1 => Some(&mut self.ch1.env),
+            2 => Some(&mut self.ch2.env),
+            3 => Some(&mut self.ch3.env),
+            4 => Some(&mut self.ch4.env),
+            _ => None,
+        }
+    }
+
+    /// Status register read. Address is relative to PSG.
+    pub fn read_status(&self, addr: usize) -> Result<i32, ()> {
+        let vol = match addr >> 4 {
+            0 => self.ch0.get_vol(),
+            1 => self.ch1.get_vol(),
+            2 => self.ch2.get_vol(),
+            3 => self.ch3.get_vol(),
+            4 => self.ch4.get_vol(),
+            _ => return Err(()),
+        };
+        let env = self.env(addr >> 4).ok_or(())?;
+        match addr & 0xf {
+            0x5 => Ok((env.get_position() * 255.) as i32),
+            0x6 => {
+                let gate = env.get_gate_value();
+                let mut status = 0;
+                if gate && vol > 0. && !(env.get_mask_vol() && env.get_value() == 0.) {
+                    status |= STATUS_ACTIVE;
+                }
+                if gate {
+                    status |= STATUS_GATE;
+                }
+                if env.is_done() {
+                    status |= STATUS_ENV_DONE;
+                }
+                Ok(status)
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Register write. Address is relative to PSG.
+    pub fn write(&mut self, addr: usize, value: i32) -> Result<(), ()> {
+        match addr {
+            // ch0
+            0x00 => self.ch0.set_freq(key_freq(value)),
+            0x01 => self.ch0.set_vol(value),
+            0x02 => self.ch0.set_pw(value),
+            0x03 => self.ch0.set_env_mask(value),
+            0x04 => self.ch0.set_env_length(value),
+            // ch1
+            0x10 => self.ch1.set_freq(key_freq(value)),
+            0x11 => self.ch1.set_vol(value),
+            0x12 => self.ch1.set_pw(value),
+            0x13 => self.ch1.set_env_mask(value),
+            0x14 => self.ch1.set_env_length(value),
+            // ch2
+            0x20 => self.ch2.set_freq(key_freq(value)),
+            0x21 => self.ch2.set_vol(value),
+            0x22 => self.ch2.set_dc(value),
+            0x23 => self.ch2.set_env_mask(value),
+            0x24 => self.ch2.set_env_length(value),
+            // ch3
+            0x30 => self.ch3.set_freq(key_freq(value)),
+            0x31 => self.ch3.set_vol(value),
+            //0x32 =>
+            0x33 => self.ch3.set_env_mask(value),
+            0x34 => self.ch3.set_env_length(value),
+            // ch4
+            0x40 => self.ch4.set_freq(key_freq(value)),
+            0x41 => self.ch4.set_vol(value),
+            0x42 => self.ch4.set_rate(value),
+            0x43 => self.ch4.set_env_mask(value),
+            0x44 => self.ch4.set_env_length(value),
+            0x47 | 0x48 => (), // Sample location is used when loading samples.
+            0x49 => self.ch4.set_loop(value),
+            0x4a => self.ch4.trigger(value != 0),
+            // Gate and ADSR, all channels
+            0x06 | 0x16 | 0x26 | 0x36 | 0x46 => self.env_mut(addr >> 4).ok_or(())?.set_key(value != 0),
+            0x0c | 0x1c | 0x2c | 0x3c | 0x4c => self.env_mut(addr >> 4).ok_or(())?.set_attack(value),
+            0x0d | 0x1d | 0x2d | 0x3d | 0x4d => self.env_mut(addr >> 4).ok_or(())?.set_decay(value),
+            0x0e | 0x1e | 0x2e | 0x3e | 0x4e => self.env_mut(addr >> 4).ok_or(())?.set_sustain(value),
+            0x0f | 0x1f | 0x2f | 0x3f | 0x4f => self.env_mut(addr >> 4).ok_or(())?.set_release(value),
+            // Mixer
+            0x0b | 0x1b | 0x2b | 0x3b | 0x4b => self.mixer.set_pan(addr >> 4, value),
+            0xf0 => self.mixer.set_master(value),
+            0xf1 => self.mixer.set_mute(value),
+            0xf2 => self.mixer.set_solo(value),
+
+            _ => return Err(()),
+        }
+        Ok(())
+    }
+}
+
+impl ChannelBank {
+    /// Sample data for ch4.
+    pub fn set_samples(&mut self, samples: Arc<[i8]>) {
+        self.ch4.set_samples(samples);
+    }
+
+    /// Next left and right sample.
+    pub fn get_next_frame(&mut self) -> [f32; 2] {
+        self.outputs = [
+            self.ch0.get_next_sample(),
+            self.ch1.get_next_sample(),
+            self.ch2.get_next_sample(),
+            self.ch3.get_next_sample(),
+            self.ch4.get_next_sample(),
+        ];
+        self.mixer.mix(&self.outputs)
+    }
+}
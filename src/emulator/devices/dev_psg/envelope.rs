@@ -1,6 +1,10 @@
 //!
 //! Envelope generator used by the audio channels
 //!
+//! By default the envelope is a linear rise or fall over its length, optionally looping. In ADSR
+//! mode it follows attack, decay, sustain and release instead, driven by a gate (key on/off).
+//! Notes then sustain while the key is held, and fade out after it's released. The other flags
+//! still choose what the envelope controls, but falling and looping are ignored in ADSR mode.
 //!
 
 /// Envelope Controls Volume (Manual volume control should still apply over this).
@@ -13,12 +17,32 @@ const FLAG_FREQ: i32 = 4;
 const FLAG_GATE: i32 = 8;
 /// Falling instead of rising: Envelope goes from 100% to 0% instead of 0% to 100%.
 const FLAG_FALLING: i32 = 16;
+/// ADSR mode. With the gate flag, channel is muted when release has finished.
+const FLAG_ADSR: i32 = 32;
 /// Loop. Envelope timer starts over if it runs out.
 const FLAG_LOOP: i32 = 64;
 /// If looping, every other cycle is reversed (rise-fall-rise-fall...).
 const FLAG_LOOP_MIRROR: i32 = 128;
 
+/// Short names of the flags, for debugging.
+const FLAG_NAMES: [(i32, &str); 8] = [
+    (FLAG_VOL, "VOL"),
+    (FLAG_PW, "PW"),
+    (FLAG_FREQ, "FREQ"),
+    (FLAG_GATE, "GATE"),
+    (FLAG_FALLING, "FALL"),
+    (FLAG_ADSR, "ADSR"),
+    (FLAG_LOOP, "LOOP"),
+    (FLAG_LOOP_MIRROR, "MIRROR"),
+];
+
+/// Names of the flags set in an envelope bitmask.
+pub(crate) fn flag_names(mask: i32) -> Vec<&'static str> {
+    FLAG_NAMES.iter().filter(|(flag, _)| mask & flag != 0).map(|(_, name)| *name).collect()
+}
+
 /// Envelope generator used by the audio channels
+#[derive(Clone)]
 pub(crate) struct Envelope {
     /// Envelope options bitmask. Flags can be found at [module][self] constants.
     mask: i32,
@@ -28,6 +52,97 @@ pub(crate) struct Envelope {
     length: f32,
     /// Envelope output value
     value: f32,
+    /// ADSR settings and state
+    adsr: Adsr,
+}
+
+/// ADSR stage
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    /// Released and silent, or never started
+    Off,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Attack => "attack",
+            Stage::Decay => "decay",
+            Stage::Sustain => "sustain",
+            Stage::Release => "release",
+            Stage::Off => "off",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Adsr {
+    /// Time to rise from silent to full in seconds
+    attack: f32,
+    /// Time to fall from full to silent in seconds. Decay stops at sustain level.
+    decay: f32,
+    /// Level held while the key is on. Range: 0 to 1
+    sustain: f32,
+    /// Time to fall from full to silent in seconds, after the key is released.
+    release: f32,
+    stage: Stage,
+    /// Range: 0 to 1
+    level: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Adsr {
+            attack: 0.,
+            decay: 0.,
+            sustain: 1.,
+            release: 0.,
+            stage: Stage::Off,
+            level: 0.,
+        }
+    }
+}
+
+impl Adsr {
+    fn update(&mut self, delta_t: f32) {
+        match self.stage {
+            Stage::Attack => {
+                self.level += rate(delta_t, self.attack);
+                if self.level >= 1. {
+                    self.level = 1.;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= rate(delta_t, self.decay);
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.sustain,
+            Stage::Release => {
+                self.level -= rate(delta_t, self.release);
+                if self.level <= 0. {
+                    self.level = 0.;
+                    self.stage = Stage::Off;
+                }
+            }
+            Stage::Off => (),
+        }
+    }
+}
+
+/// How much a stage of `length` seconds moves in `delta_t`. Zero length moves all the way.
+fn rate(delta_t: f32, length: f32) -> f32 {
+    match length > 0. {
+        true => delta_t / length,
+        false => 1.,
+    }
 }
 
 impl Default for Envelope {
@@ -37,6 +152,7 @@ impl Default for Envelope {
             timer: 0.,
             length: 1.,
             value: 0.,
+            adsr: Adsr::default(),
         }
     }
 }
@@ -45,6 +161,12 @@ impl Envelope {
     /// It is advisable to call this every time a sample is generated.  
     /// delta_t: Time since last update in seconds
     pub fn update(&mut self, delta_t: f32) {
+        if self.mask & FLAG_ADSR != 0 {
+            self.adsr.update(delta_t);
+            self.value = self.adsr.level;
+            return;
+        }
+
         // Update the timer
         self.timer += delta_t / self.length;
 
@@ -99,6 +221,10 @@ impl Envelope {
         if self.mask & FLAG_GATE == 0 {
             return true;
         }
+        // ADSR: open until release has finished
+        if self.mask & FLAG_ADSR != 0 {
+            return self.adsr.stage != Stage::Off;
+        }
         // If looping is enabled, the timer should never pass 1, but..
         // This check was necessary because of how we implemented mirrored loop. See update fn.
         if self.mask & FLAG_LOOP == 1 {
@@ -112,6 +238,33 @@ impl Envelope {
     pub fn get_value(&self) -> f32 {
         self.value
     }
+    /// Position in the envelope, or level in ADSR mode. Range: 0 to 1
+    #[inline]
+    pub fn get_position(&self) -> f32 {
+        if self.mask & FLAG_ADSR != 0 {
+            return self.adsr.level;
+        }
+        match self.mask & FLAG_LOOP != 0 {
+            true => self.timer.fract(),
+            false => self.timer.min(1.),
+        }
+    }
+    /// Envelope has run out, and isn't looping. In ADSR mode, release has finished.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        if self.mask & FLAG_ADSR != 0 {
+            return self.adsr.stage == Stage::Off;
+        }
+        self.mask & FLAG_LOOP == 0 && self.timer >= 1.0
+    }
+    /// Current ADSR stage, if in ADSR mode
+    #[inline]
+    pub fn get_stage(&self) -> Option<Stage> {
+        match self.mask & FLAG_ADSR != 0 {
+            true => Some(self.adsr.stage),
+            false => None,
+        }
+    }
 
     // Setters
 
@@ -127,4 +280,32 @@ impl Envelope {
     pub fn reset_timer(&mut self) {
         self.timer = 0.;
     }
+    /// Key on starts attack from the current level, key off starts release. Key on also restarts
+    /// the linear envelope.
+    pub fn set_key(&mut self, on: bool) {
+        match on {
+            true => {
+                self.adsr.stage = Stage::Attack;
+                self.timer = 0.;
+            }
+            false if self.adsr.stage != Stage::Off => self.adsr.stage = Stage::Release,
+            false => (),
+        }
+    }
+    /// Expected input range: 0..=i32::MAX (ms)
+    pub fn set_attack(&mut self, value: i32) {
+        self.adsr.attack = value.max(0) as f32 / 1000.;
+    }
+    /// Expected input range: 0..=i32::MAX (ms)
+    pub fn set_decay(&mut self, value: i32) {
+        self.adsr.decay = value.max(0) as f32 / 1000.;
+    }
+    /// Expected input range: 0..=255
+    pub fn set_sustain(&mut self, value: i32) {
+        self.adsr.sustain = (value & 0xff) as f32 / 255.;
+    }
+    /// Expected input range: 0..=i32::MAX (ms)
+    pub fn set_release(&mut self, value: i32) {
+        self.adsr.release = value.max(0) as f32 / 1000.;
+    }
 }
@@ -0,0 +1,76 @@
+//!
+//! Mixer: pans each channel, and combines them into stereo output
+//!
+//! The program controls pan, master volume, mute and solo through registers. The host has its own
+//! mute and solo masks on top of those, for listening to channels separately. They only change what
+//! plays through the speakers, not what the program sees or what gets recorded.
+//!
+
+/// Number of mixer inputs
+pub(crate) const CHANNELS: usize = 5;
+
+#[derive(Clone)]
+pub(crate) struct Mixer {
+    /// Range: -1.0 (left) to 1.0 (right)
+    pan: [f32; CHANNELS],
+    /// Range: 0.0 to 1.0
+    master: f32,
+    /// Bit n mutes channel n.
+    mute: i32,
+    /// If any bits are set, only those channels play.
+    solo: i32,
+    pub host_mute: i32,
+    pub host_solo: i32,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer {
+            pan: [0.; CHANNELS],
+            master: 1.,
+            mute: 0,
+            solo: 0,
+            host_mute: 0,
+            host_solo: 0,
+        }
+    }
+}
+
+impl Mixer {
+    /// Expected input range: -128 (left) to 128 (right). 0 is center.
+    pub fn set_pan(&mut self, channel: usize, value: i32) {
+        self.pan[channel] = value.clamp(-128, 128) as f32 / 128.;
+    }
+    /// Expected input range: 0..=255
+    pub fn set_master(&mut self, value: i32) {
+        self.master = (value & 0xff) as f32 / 255.;
+    }
+    pub fn set_mute(&mut self, value: i32) {
+        self.mute = value;
+    }
+    pub fn set_solo(&mut self, value: i32) {
+        self.solo = value;
+    }
+
+    /// Whether a channel can be heard, after mute and solo.
+    pub fn is_audible(&self, channel: usize) -> bool {
+        let bit = 1 << channel;
+        let solo = self.solo | self.host_solo;
+        (self.mute | self.host_mute) & bit == 0 && (solo == 0 || solo & bit != 0)
+    }
+
+    /// Mix channel samples into a left and right sample.
+    pub fn mix(&self, samples: &[f32; CHANNELS]) -> [f32; 2] {
+        let mut out = [0.; 2];
+        for (channel, sample) in samples.iter().enumerate() {
+            if !self.is_audible(channel) {
+                continue;
+            }
+            // Center plays at full volume on both sides, like mono output did.
+            let pan = self.pan[channel];
+            out[0] += sample * (1. - pan).min(1.);
+            out[1] += sample * (1. + pan).min(1.);
+        }
+        out.map(|sample| sample * self.master)
+    }
+}
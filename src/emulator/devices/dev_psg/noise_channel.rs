@@ -6,6 +6,7 @@
 use super::{envelope::Envelope, AudioChannel, SAMPLE_RATE};
 
 /// Noise generator
+#[derive(Clone)]
 pub struct NoiseChannel {
     /// Position in current cycle. Range: 0.0 to 1.0
     cycletimer: f32,
@@ -32,6 +33,10 @@ impl NoiseChannel {
         self.vol = (value & 0xff) as f32 / 255.;
         self.env.reset_timer();
     }
+    /// Volume range: 0.0 to 1.0
+    pub fn get_vol(&self) -> f32 {
+        self.vol
+    }
     /// Set envelope bitmask.
     pub fn set_env_mask(&mut self, value: i32) {
         self.env.set_mask(value);
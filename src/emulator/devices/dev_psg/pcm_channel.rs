@@ -0,0 +1,160 @@
+//!
+//! PCM sample / wavetable player
+//!
+//! Plays 8-bit signed samples copied from emulated memory. A short looped sample works as a
+//! wavetable: setting a note plays the loop once per period of the note.
+//!
+
+use super::{envelope::Envelope, AudioChannel, SAMPLE_RATE};
+use std::sync::Arc;
+
+/// How playback speed is chosen.
+#[derive(Clone, Copy)]
+enum Pitch {
+    /// Loop plays at this frequency in Hz.
+    Note(f32),
+    /// Samples per second.
+    Rate(f32),
+}
+
+/// PCM sample / wavetable player
+#[derive(Clone)]
+pub struct PcmChannel {
+    samples: Arc<[i8]>,
+    /// Sample data waiting for playback to start.
+    pending: Option<Arc<[i8]>>,
+    /// Position in samples.
+    pos: f64,
+    playing: bool,
+    pitch: Pitch,
+    /// Volume range: 0.0 to 1.0
+    vol: f32,
+    /// Where playback continues after the end. Negative means no loop.
+    loop_point: i32,
+    /// Envelope.
+    pub env: Envelope,
+}
+
+impl PcmChannel {
+    /// Expected input range: 0..=i32::MAX
+    pub fn set_freq(&mut self, value: f32) {
+        self.pitch = Pitch::Note(value);
+        self.env.reset_timer();
+    }
+    /// Samples per second. Expected input range: 0..=i32::MAX
+    pub fn set_rate(&mut self, value: i32) {
+        self.pitch = Pitch::Rate(value.max(0) as f32);
+    }
+    /// Expected input range: 0..=255
+    pub fn set_vol(&mut self, value: i32) {
+        self.vol = (value & 0xff) as f32 / 255.;
+        self.env.reset_timer();
+    }
+    /// Volume range: 0.0 to 1.0. Stopped channel has no volume.
+    pub fn get_vol(&self) -> f32 {
+        match self.playing {
+            true => self.vol,
+            false => 0.,
+        }
+    }
+    /// Set envelope bitmask.
+    pub fn set_env_mask(&mut self, value: i32) {
+        self.env.set_mask(value);
+        self.env.reset_timer();
+    }
+    /// Expected input range: 0..=i32::MAX
+    pub fn set_env_length(&mut self, value: i32) {
+        self.env.set_length(value.max(0) as f32 / 1000.);
+        self.env.reset_timer();
+    }
+    /// Offset in samples. Negative disables looping.
+    pub fn set_loop(&mut self, value: i32) {
+        self.loop_point = value;
+    }
+    /// Replace sample data. Takes effect when playback is started, so the position never points
+    /// past the end of the data.
+    pub fn set_samples(&mut self, samples: Arc<[i8]>) {
+        self.pending = Some(samples);
+    }
+    /// Start playing from the beginning, or stop if `play` is false.
+    pub fn trigger(&mut self, play: bool) {
+        if play {
+            if let Some(samples) = self.pending.take() {
+                self.samples = samples;
+            }
+        }
+        self.pos = 0.;
+        self.playing = play;
+        self.env.reset_timer();
+    }
+
+    /// Loop start, if the loop is within the sample.
+    fn loop_start(&self) -> Option<usize> {
+        usize::try_from(self.loop_point).ok().filter(|&start| start < self.samples.len())
+    }
+}
+
+impl Default for PcmChannel {
+    fn default() -> Self {
+        PcmChannel {
+            samples: Arc::new([]),
+            pending: None,
+            pos: 0.,
+            playing: false,
+            pitch: Pitch::Rate(SAMPLE_RATE as f32),
+            vol: 0.,
+            loop_point: -1,
+            env: Envelope::default(),
+        }
+    }
+}
+
+impl AudioChannel for PcmChannel {
+    fn get_next_sample(&mut self) -> f32 {
+        let delta_t = 1. / SAMPLE_RATE as f32;
+
+        // Envelope
+        self.env.update(delta_t);
+        let env_value = self.env.get_value();
+
+        let len = self.samples.len();
+        if !self.playing || len == 0 {
+            return 0.;
+        }
+
+        // Volume
+        let mut vol = self.vol;
+        if self.env.get_mask_vol() {
+            vol *= env_value;
+        }
+        if !self.env.get_gate_value() {
+            vol = 0.;
+        }
+        vol = vol.clamp(0., 1.);
+
+        let output = self.samples[self.pos as usize] as f32 / 128. * vol;
+
+        // Advance. Looping notes play the loop once per period, otherwise the whole sample.
+        let loop_start = self.loop_start();
+        let step = match self.pitch {
+            Pitch::Note(freq) => freq * (len - loop_start.unwrap_or(0)) as f32,
+            Pitch::Rate(rate) => rate,
+        };
+        let mut step = step as f64 / SAMPLE_RATE as f64;
+        if self.env.get_mask_freq() {
+            step *= 1. + env_value as f64;
+        }
+        self.pos += step;
+        if self.pos >= len as f64 {
+            match loop_start {
+                Some(start) => {
+                    let loop_len = (len - start) as f64;
+                    self.pos = start as f64 + (self.pos - len as f64) % loop_len;
+                }
+                None => self.playing = false,
+            }
+        }
+
+        output
+    }
+}
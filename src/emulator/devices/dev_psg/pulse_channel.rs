@@ -6,6 +6,7 @@
 use super::{envelope::Envelope, AudioChannel, SAMPLE_RATE};
 
 /// Pulse wave generator
+#[derive(Clone)]
 pub struct PulseChannel {
     /// Position in current cycle. Range: 0.0 to 1.0
     cycletimer: f32,
@@ -36,6 +37,10 @@ impl PulseChannel {
         self.pw = (value & 0xff) as f32 / 255.;
         self.env.reset_timer();
     }
+    /// Volume range: 0.0 to 1.0
+    pub fn get_vol(&self) -> f32 {
+        self.vol
+    }
     /// Set envelope bitmask.
     pub fn set_env_mask(&mut self, value: i32) {
         self.env.set_mask(value);
@@ -54,7 +59,7 @@ impl Default for PulseChannel {
             cycletimer: 0.,
             freq: 440.,
             vol: 0.,
-            pw: 0.5,
+            pw: 128. / 255.,
             env: Envelope::default(),
         }
     }
@@ -6,6 +6,7 @@
 use super::{envelope::Envelope, AudioChannel, SAMPLE_RATE};
 
 /// Ramp wave generator
+#[derive(Clone)]
 pub struct RampChannel {
     /// Position in current cycle. Range: 0.0 to 1.0
     cycletimer: f32,
@@ -35,6 +36,10 @@ impl RampChannel {
         self.dc = (value & 0xff) as f32 / 255.;
         self.env.reset_timer();
     }
+    /// Volume range: 0.0 to 1.0
+    pub fn get_vol(&self) -> f32 {
+        self.vol
+    }
     /// Set envelope bitmask.
     pub fn set_env_mask(&mut self, value: i32) {
         self.env.set_mask(value);
@@ -53,7 +58,7 @@ impl Default for RampChannel {
             cycletimer: 0.,
             freq: 440.,
             vol: 0.,
-            dc: 0.5,
+            dc: 128. / 255.,
             env: Envelope::default(),
         }
     }
@@ -0,0 +1,152 @@
+//!
+//! Timestamped register writes
+//!
+//! Register writes are queued with the emulated time they happened at, counted in samples, and
+//! applied to the channels when the output reaches that sample. Timing is then decided by the
+//! emulated machine alone, not by when the host happens to run the emulator or the audio thread.
+//!
+//! Live output pulls samples on wall-clock time, so it can't always follow emulated time exactly:
+//! - When emulation falls behind, the output holds its position and keeps playing the current
+//!   state, until there's emulated time to play again.
+//! - When emulation runs ahead, the output skips forward to stay within MAX_LAG of it. Skipped
+//!   writes are still applied, in order.
+//!
+//! Writes that fall more than MAX_LAG behind are applied as soon as new ones come in, so the queue
+//! stays bounded even when nothing pulls samples, e.g. without an audio device.
+//!
+
+use super::channel_bank::ChannelBank;
+use super::SAMPLE_RATE;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Live output stays this far behind emulated time, so it has something to play between the
+/// emulator's bursts of ticks. In samples.
+pub(crate) const LATENCY: u64 = SAMPLE_RATE as u64 / 20;
+/// Live output is never further than this behind emulated time. In samples.
+pub(crate) const MAX_LAG: u64 = SAMPLE_RATE as u64 / 4;
+
+/// Change waiting for its time.
+#[derive(Clone)]
+struct Event {
+    /// Emulated time in samples
+    time: u64,
+    command: Command,
+}
+
+#[derive(Clone)]
+enum Command {
+    Write(usize, i32),
+    /// New sample data for the PCM channel.
+    Samples(Arc<[i8]>),
+}
+
+/// Channels and their queue of register writes.
+#[derive(Clone, Default)]
+pub(crate) struct Sequencer {
+    pub bank: ChannelBank,
+    events: VecDeque<Event>,
+    /// Emulated time of the next output sample.
+    pub position: u64,
+    /// Latest emulated time, for live output. None when output isn't tied to emulated time.
+    pub now: Option<u64>,
+}
+
+impl Sequencer {
+    /// Sequencer for live output, starting at emulated time 0.
+    pub fn live() -> Self {
+        Self { now: Some(0), ..Default::default() }
+    }
+
+    /// Copy of the current state that renders without following emulated time, or host mute and
+    /// solo.
+    pub fn offline(&self) -> Self {
+        let mut seq = Self { now: None, ..self.clone() };
+        seq.bank.mixer.host_mute = 0;
+        seq.bank.mixer.host_solo = 0;
+        seq
+    }
+
+    /// Queue a register write to happen at emulated time `time`, in samples.
+    /// Checks that the register exists, so the write can fail at the time it's made.
+    pub fn push(&mut self, time: u64, addr: usize, value: i32) -> Result<(), ()> {
+        self.bank.check(addr)?;
+        self.events.push_back(Event { time, command: Command::Write(addr, value) });
+        self.skip_stale();
+        Ok(())
+    }
+
+    /// Queue new sample data for the PCM channel.
+    pub fn push_samples(&mut self, time: u64, samples: Arc<[i8]>) {
+        self.events.push_back(Event { time, command: Command::Samples(samples) });
+        self.skip_stale();
+    }
+
+    /// Number of changes waiting for their time.
+    #[cfg(test)]
+    pub fn queued(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Start emulated time over from 0. Writes still waiting are applied right away.
+    pub fn restart(&mut self) {
+        while let Some(event) = self.events.pop_front() {
+            self.run(event.command);
+        }
+        self.position = 0;
+        if self.now.is_some() {
+            self.now = Some(0);
+        }
+    }
+
+    /// Channels with all writes up to current position applied.
+    pub fn current(&mut self) -> &ChannelBank {
+        self.apply_due();
+        &self.bank
+    }
+
+    /// Skip live output forward to MAX_LAG behind emulated time, like `get_next_frame()` would.
+    fn skip_stale(&mut self) {
+        let Some(now) = self.now else {
+            return;
+        };
+        self.position = self.position.max(now.saturating_sub(MAX_LAG));
+        self.apply_due();
+    }
+
+    /// Apply all writes up to current position.
+    fn apply_due(&mut self) {
+        while self.events.front().is_some_and(|event| event.time <= self.position) {
+            if let Some(event) = self.events.pop_front() {
+                self.run(event.command);
+            }
+        }
+    }
+
+    fn run(&mut self, command: Command) {
+        match command {
+            Command::Write(addr, value) => {
+                let _ = self.bank.write(addr, value);
+            }
+            Command::Samples(samples) => self.bank.set_samples(samples),
+        }
+    }
+}
+
+impl Sequencer {
+    /// Next left and right sample.
+    pub fn get_next_frame(&mut self) -> [f32; 2] {
+        let mut advance = true;
+        if let Some(now) = self.now {
+            // Catch up, or wait for emulation.
+            self.position = self.position.max(now.saturating_sub(MAX_LAG));
+            advance = self.position + LATENCY < now;
+        }
+        self.apply_due();
+        if advance {
+            self.position += 1;
+        }
+        self.bank.get_next_frame()
+    }
+}
+
@@ -0,0 +1,50 @@
+//!
+//! WAV file writer for recording PSG output.
+//!
+//! The recording is 16-bit stereo PCM at the PSG sample rate. Samples are rendered in emulated time,
+//! so the file sounds like the program would on the real thing, however fast the emulator ran.
+//!
+
+use super::SAMPLE_RATE;
+
+/// Collects samples, and turns them into a .wav file.
+#[derive(Default)]
+pub(crate) struct WavRecorder {
+    samples: Vec<i16>,
+}
+
+impl WavRecorder {
+    /// Add a left and right sample. Values outside -1.0 to 1.0 are clipped.
+    pub fn push(&mut self, frame: [f32; 2]) {
+        for sample in frame {
+            self.samples.push((sample.clamp(-1., 1.) * i16::MAX as f32) as i16);
+        }
+    }
+
+    /// Build the complete file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        const CHANNELS: u16 = 2;
+        const BITS: u16 = 16;
+        let block_align = CHANNELS * BITS / 8;
+        let data_len = (self.samples.len() * BITS as usize / 8) as u32;
+
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        bytes.extend_from_slice(&CHANNELS.to_le_bytes());
+        bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        bytes.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes()); // Byte rate
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&BITS.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        bytes
+    }
+}
@@ -0,0 +1,207 @@
+//!
+//! Raw keyboard
+//!
+//! Unlike =KBD, reading this keyboard never blocks. The GUI queues keyboard input while the
+//! Graphics Display has focus, and the program can poll it, or wait for an interrupt.
+//!
+//! There are two separate queues:
+//! - Characters: Typed text, as Unicode code points. Shift, keymaps, etc. are already applied.
+//! - Key events: Presses and releases of individual keys.
+//!
+//! Key event format:
+//! | Bits  | Meaning                           |
+//! | ----- | --------------------------------- |
+//! | 0-7   | Key code                          |
+//! | 8     | 0: Press, 1: Release              |
+//!
+//! Key codes are the same as Windows virtual-key codes (and JavaScript `keyCode`):
+//! | Code  | Key                   |
+//! | ----- | --------------------- |
+//! | 8     | Backspace             |
+//! | 9     | Tab                   |
+//! | 13    | Enter                 |
+//! | 16    | Shift                 |
+//! | 17    | Ctrl                  |
+//! | 18    | Alt                   |
+//! | 27    | Escape                |
+//! | 32    | Space                 |
+//! | 37-40 | Left, Up, Right, Down |
+//! | 48-57 | 0-9                   |
+//! | 65-90 | A-Z                   |
+//! | 112-  | F1-                   |
+//!
+//! Ports:
+//!  - Port 0: Status (global port 0x50)
+//!  - Port 1: Character (global port 0x51)
+//!  - Port 2: Key event (global port 0x52)
+//!
+//! Status bits:
+//! | Bit | Meaning                                              |
+//! | --- | ---------------------------------------------------- |
+//! | 0   | Character available                                  |
+//! | 1   | Key event available                                  |
+//! | 2   | Overflow: input was lost because a queue was full    |
+//!
+//! Read Behaviour:
+//! | Port | Effect                                                      |
+//! | ---- | ----------------------------------------------------------- |
+//! | 0    | Returns status, and clears the overflow bit                 |
+//! | 1    | Returns next character from the queue. 0 if empty.          |
+//! | 2    | Returns next key event from the queue. 0 if empty.          |
+//!
+//! Write Behaviour:
+//! | Port | Effect                |
+//! | ---- | --------------------- |
+//! | 0    | Clear both queues     |
+//!
+//! Any new input raises the keyboard interrupt (IVT entry 7), if it's enabled in the PIC.
+//!
+use super::{Device, PMIO};
+use std::collections::VecDeque;
+
+/// Queue size. Anything beyond is dropped.
+const QUEUE_LEN: usize = 64;
+/// Key event bit for releases.
+const KEY_RELEASE: i32 = 0x100;
+
+const STATUS_CHAR: i32 = 0b_001;
+const STATUS_KEY: i32 = 0b_010;
+const STATUS_OVERFLOW: i32 = 0b_100;
+
+/// Raw keyboard
+#[derive(Default)]
+pub(crate) struct DevRawKBD {
+    chars: VecDeque<i32>,
+    keys: VecDeque<i32>,
+    overflow: bool,
+    /// Set when new input arrives. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl DevRawKBD {
+    /// Queue a typed character. Emulator calls this when GUI sends one.
+    pub fn push_char(&mut self, c: i32) {
+        push(&mut self.chars, c, &mut self.overflow);
+        self.interrupt = true;
+    }
+
+    /// Queue a key event. Emulator calls this when GUI sends one.
+    pub fn push_key(&mut self, code: i32, pressed: bool) {
+        let event = match pressed {
+            true => code & 0xff,
+            false => code & 0xff | KEY_RELEASE,
+        };
+        push(&mut self.keys, event, &mut self.overflow);
+        self.interrupt = true;
+    }
+
+    fn clear(&mut self) {
+        self.chars.clear();
+        self.keys.clear();
+        self.overflow = false;
+        self.interrupt = false;
+    }
+}
+
+fn push(queue: &mut VecDeque<i32>, value: i32, overflow: &mut bool) {
+    if queue.len() >= QUEUE_LEN {
+        *overflow = true;
+        return;
+    }
+    queue.push_back(value);
+}
+
+impl Device for DevRawKBD {
+    fn reset(&mut self) {
+        self.clear();
+    }
+    fn on(&mut self) {
+        self.clear();
+    }
+    fn off(&mut self) {}
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevRawKBD {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => {
+                let mut status = 0;
+                if !self.chars.is_empty() {
+                    status |= STATUS_CHAR;
+                }
+                if !self.keys.is_empty() {
+                    status |= STATUS_KEY;
+                }
+                if std::mem::take(&mut self.overflow) {
+                    status |= STATUS_OVERFLOW;
+                }
+                Ok(status)
+            }
+            1 => Ok(self.chars.pop_front().unwrap_or(0)),
+            2 => Ok(self.keys.pop_front().unwrap_or(0)),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, _value: i32) -> Result<(), ()> {
+        match port {
+            0 => {
+                self.chars.clear();
+                self.keys.clear();
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_rawkbd() -> Result<(), ()> {
+        let mut kbd = DevRawKBD::default();
+
+        // Test wrong usage
+        assert!(kbd.read_port(3).is_err());
+        assert!(kbd.write_port(1, 0).is_err());
+        assert!(kbd.write_port(2, 55).is_err());
+
+        // Empty reads don't block
+        assert_eq!(kbd.read_port(0)?, 0);
+        assert_eq!(kbd.read_port(1)?, 0);
+        assert_eq!(kbd.read_port(2)?, 0);
+        assert!(!kbd.interrupt);
+
+        // Type "Hi": shift down, h down, i, shift up...
+        kbd.push_key(16, true);
+        kbd.push_key(72, true);
+        kbd.push_char('H' as i32);
+        kbd.push_key(72, false);
+        kbd.push_key(16, false);
+        kbd.push_char('i' as i32);
+        assert!(kbd.interrupt);
+        assert_eq!(kbd.read_port(0)?, STATUS_CHAR | STATUS_KEY);
+        assert_eq!(kbd.read_port(1)?, 'H' as i32);
+        assert_eq!(kbd.read_port(1)?, 'i' as i32);
+        assert_eq!(kbd.read_port(1)?, 0);
+        assert_eq!(kbd.read_port(0)?, STATUS_KEY);
+        assert_eq!(kbd.read_port(2)?, 16);
+        assert_eq!(kbd.read_port(2)?, 72);
+        assert_eq!(kbd.read_port(2)?, 72 | KEY_RELEASE);
+        assert_eq!(kbd.read_port(2)?, 16 | KEY_RELEASE);
+        assert_eq!(kbd.read_port(0)?, 0);
+
+        // Overflow
+        for _ in 0..QUEUE_LEN + 1 {
+            kbd.push_char('a' as i32);
+        }
+        assert_eq!(kbd.read_port(0)?, STATUS_CHAR | STATUS_OVERFLOW);
+        assert_eq!(kbd.read_port(0)?, STATUS_CHAR);
+        kbd.write_port(0, 0)?;
+        assert_eq!(kbd.read_port(0)?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//!
+//! Random number generator.
+//!
+//! Returns pseudo-random 32-bit values. The generator can be reseeded by the program, and in
+//! deterministic mode it's seeded from the config every time the machine is turned on, so a
+//! program gets the same numbers on every run. Otherwise it's seeded from the host clock.
+//!
+//! Ports:
+//!  - Port 0: Random value (global port 3)
+//!  - Port 1: Seed (global port 4)
+//!
+//! | Port | Read                            | Write                                 |
+//! | ---- | ------------------------------- | ------------------------------------- |
+//! | 0    | Next random value               | Error                                 |
+//! | 1    | Last seed                       | Reseed. Same seed, same sequence.     |
+//!
+use super::{Device, PMIO};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Random number generator.
+#[derive(Default)]
+pub(crate) struct DevRNG {
+    /// Seed used on power on. None seeds from the host clock.
+    config_seed: Option<i32>,
+    seed: i32,
+    state: u64,
+}
+
+impl DevRNG {
+    /// Set deterministic seed. Takes effect next time the machine is turned on.
+    pub fn set_seed(&mut self, seed: Option<i32>) {
+        self.config_seed = seed;
+    }
+
+    fn reseed(&mut self, seed: i32) {
+        self.seed = seed;
+        self.state = seed as u32 as u64;
+    }
+
+    /// SplitMix64, upper half of the output.
+    fn next(&mut self) -> i32 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 32) as i32
+    }
+}
+
+impl Device for DevRNG {
+    fn reset(&mut self) {}
+    fn on(&mut self) {
+        let seed = self.config_seed.unwrap_or_else(|| {
+            let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_nanos()).unwrap_or(0);
+            nanos as i32
+        });
+        self.reseed(seed);
+    }
+    fn off(&mut self) {}
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevRNG {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.next()),
+            1 => Ok(self.seed),
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        match port {
+            1 => {
+                self.reseed(value);
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_rng() -> Result<(), ()> {
+        let mut rng = DevRNG::default();
+        rng.set_seed(Some(1234));
+        rng.on();
+
+        // Test wrong usage
+        assert!(rng.read_port(2).is_err());
+        assert!(rng.write_port(0, 0).is_err());
+        assert!(rng.write_port(2, 0).is_err());
+
+        // Deterministic seed gives the same sequence on every power on.
+        assert_eq!(rng.read_port(1)?, 1234);
+        let first: Vec<i32> = (0..8).map(|_| rng.read_port(0).unwrap()).collect();
+        rng.off();
+        rng.on();
+        let second: Vec<i32> = (0..8).map(|_| rng.read_port(0).unwrap()).collect();
+        assert_eq!(first, second);
+        // ...and it's not stuck.
+        assert!(first.windows(2).all(|w| w[0] != w[1]));
+
+        // Reseeding restarts the sequence.
+        rng.write_port(1, 1234)?;
+        assert_eq!(rng.read_port(0)?, first[0]);
+        rng.write_port(1, -1)?;
+        assert_eq!(rng.read_port(1)?, -1);
+        assert_ne!(rng.read_port(0)?, first[0]);
+
+        // Zero is a fine seed too.
+        rng.write_port(1, 0)?;
+        assert_ne!(rng.read_port(0)?, rng.read_port(0)?);
+
+        Ok(())
+    }
+}
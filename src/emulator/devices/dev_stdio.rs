@@ -1,5 +1,168 @@
 //!
-//!  devices/stdio.rs
+//! Titokone-style file devices =stdin and =stdout
 //!
-//! This will become titokone stdin/stdout devices
+//! Input is read from a host file, one integer per `IN`. Integers may be separated by newlines or
+//! any other whitespace. Output is appended to a host file, one integer per line.
 //!
+//! The input file is (re)read every time the machine is turned on, so each run starts from the
+//! beginning of the file, like in Titokone.
+//!
+//! Ports:
+//!  - Port 0: =STDIN  (global port 6)
+//!  - Port 1: =STDOUT (global port 7)
+//!
+//! | Port | Read                          | Write                           |
+//! | ---- | ----------------------------- | ------------------------------- |
+//! | 0    | Next integer from input file  | Error                           |
+//! | 1    | Error                         | Append value to output file     |
+//!
+//! Reading past the end of the input file, or using a device with no file set, is an error.
+//!
+use super::{Device, PMIO};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Titokone-style file devices =stdin and =stdout
+#[derive(Default)]
+pub(crate) struct DevStdIO {
+    stdin_path: Option<PathBuf>,
+    stdout_path: Option<PathBuf>,
+    /// Integers that have not been read yet.
+    stdin_buf: VecDeque<i32>,
+    /// Output file is kept open while the machine is on.
+    stdout_file: Option<File>,
+}
+
+impl DevStdIO {
+    /// Set host file for =stdin. Takes effect next time the machine is turned on.
+    pub fn set_stdin_path(&mut self, path: Option<PathBuf>) {
+        self.stdin_path = path;
+    }
+
+    /// Set host file for =stdout. Takes effect next time the machine is turned on.
+    pub fn set_stdout_path(&mut self, path: Option<PathBuf>) {
+        self.stdout_path = path;
+    }
+
+    /// Read and parse the whole input file into the buffer.
+    fn load_stdin(&mut self) {
+        self.stdin_buf.clear();
+        let Some(path) = &self.stdin_path else {
+            return;
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                println!("stdin: couldn't read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        for word in text.split_whitespace() {
+            match word.parse::<i32>() {
+                Ok(value) => self.stdin_buf.push_back(value),
+                Err(_) => println!("stdin: skipping invalid value \"{}\"", word),
+            }
+        }
+    }
+
+    /// Open the output file for appending.
+    fn open_stdout(&mut self) {
+        self.stdout_file = None;
+        let Some(path) = &self.stdout_path else {
+            return;
+        };
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => self.stdout_file = Some(file),
+            Err(e) => println!("stdout: couldn't open {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Device for DevStdIO {
+    fn reset(&mut self) {
+        self.stdin_buf.clear();
+        self.stdout_file = None;
+    }
+    fn on(&mut self) {
+        self.load_stdin();
+        self.open_stdout();
+    }
+    fn off(&mut self) {
+        self.stdin_buf.clear();
+        self.stdout_file = None;
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevStdIO {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        if port != 0 {
+            return Err(()); // You can't read from stdout!
+        }
+        self.stdin_buf.pop_front().ok_or(())
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        if port != 1 {
+            return Err(()); // You can't write into stdin!
+        }
+        match &mut self.stdout_file {
+            Some(file) => writeln!(file, "{}", value).map_err(|_| ()),
+            None => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_dev_stdio() -> Result<(), ()> {
+        let stdin_path = temp_dir().join("titomachine_test_dev_stdio_in");
+        let stdout_path = temp_dir().join("titomachine_test_dev_stdio_out");
+        fs::write(&stdin_path, "55\n-33 7\n\n 0\n").unwrap();
+        let _ = fs::remove_file(&stdout_path);
+
+        let mut stdio = DevStdIO::default();
+
+        // No files set
+        stdio.on();
+        assert!(stdio.read_port(0).is_err());
+        assert!(stdio.write_port(1, 55).is_err());
+        stdio.off();
+
+        stdio.set_stdin_path(Some(stdin_path.clone()));
+        stdio.set_stdout_path(Some(stdout_path.clone()));
+        stdio.on();
+
+        // Test wrong usage
+        assert!(stdio.read_port(1).is_err());
+        assert!(stdio.read_port(2).is_err());
+        assert!(stdio.write_port(0, 0).is_err());
+        assert!(stdio.write_port(2, 0).is_err());
+
+        // Read the whole input, and then some.
+        assert_eq!(stdio.read_port(0)?, 55);
+        assert_eq!(stdio.read_port(0)?, -33);
+        assert_eq!(stdio.read_port(0)?, 7);
+        assert_eq!(stdio.read_port(0)?, 0);
+        assert!(stdio.read_port(0).is_err());
+
+        // Output is appended, and input starts over after power cycle.
+        stdio.write_port(1, 55)?;
+        stdio.write_port(1, -33)?;
+        stdio.off();
+        stdio.on();
+        assert_eq!(stdio.read_port(0)?, 55);
+        stdio.write_port(1, 7)?;
+        stdio.off();
+        assert_eq!(fs::read_to_string(&stdout_path).unwrap(), "55\n-33\n7\n");
+
+        let _ = fs::remove_file(&stdin_path);
+        let _ = fs::remove_file(&stdout_path);
+        Ok(())
+    }
+}
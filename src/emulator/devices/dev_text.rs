@@ -0,0 +1,279 @@
+//!
+//! Text mode display
+//!
+//! Character cell display with a built-in 8x8 font, shown in its own Text panel. It's located at
+//! 0x8000-0x8fff.
+//!
+//! The screen is 25 rows of 80 or 40 columns. Each cell has a character, and an attribute byte
+//! with foreground and background colors from a fixed 16 color palette.
+//!
+//! Memory map:
+//! | Address       | Size | Contents                                        |
+//! | ------------- | ---- | ----------------------------------------------- |
+//! | 0x000 - 0x00f | 16   | Registers                                       |
+//! | 0x100 - 0x8cf | 2000 | Cells, row by row                               |
+//!
+//! Cell `(x, y)` is at `0x8100 + y * columns + x`. In 40 column mode only the first 1000 cells are
+//! shown.
+//!
+//! Everything can be read back. Unused addresses and registers are errors.
+//!
+//! Registers:
+//! | Address | Register          | Description                                              |
+//! | ------- | ----------------- | -------------------------------------------------------- |
+//! | 0x000   | Mode              | 0: 80x25, 1: 40x25                                       |
+//! | 0x001   | Cursor            | Cell index of the blinking cursor. Out of range hides it |
+//! | 0x002   | Default attribute | Attribute for cells that have none. Initially 0x07       |
+//!
+//! ### Cells
+//! | Bits  | Description                                              |
+//! | ----- | -------------------------------------------------------- |
+//! | 0-7   | Character code. Printable ASCII is supported.            |
+//! | 8-11  | Foreground color                                         |
+//! | 12-15 | Background color                                         |
+//!
+//! Bits 8-15 together are the attribute. If it's 0, default attribute is used instead, so plain
+//! character codes can be written directly. Black on black can still be had through the default
+//! attribute.
+//!
+//! ### Colors
+//! | Value | Color   | Value | Color          |
+//! | ----- | ------- | ----- | -------------- |
+//! | 0     | Black   | 8     | Dark gray      |
+//! | 1     | Blue    | 9     | Light blue     |
+//! | 2     | Green   | 10    | Light green    |
+//! | 3     | Cyan    | 11    | Light cyan     |
+//! | 4     | Red     | 12    | Light red      |
+//! | 5     | Magenta | 13    | Light magenta  |
+//! | 6     | Brown   | 14    | Yellow         |
+//! | 7     | Gray    | 15    | White          |
+//!
+use super::{Device, MMIO};
+use crate::emulator::emu_debug::TextScreen;
+use image::Rgba;
+
+mod font;
+
+use self::font::glyph;
+
+const ROWS: usize = 25;
+const CELL_SIZE: usize = 8;
+const MAX_CELLS: usize = 80 * ROWS;
+
+const ADDR_CELLS: usize = 0x100;
+
+const MODE_80: i32 = 0;
+const MODE_40: i32 = 1;
+
+const DEFAULT_ATTR: i32 = 0x07;
+/// How long the cursor stays on or off, in seconds of emulated time.
+const BLINK_TIME: f64 = 0.5;
+
+const PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xaa, 0x55, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+/// Text mode display
+pub(crate) struct DevText {
+    mode: i32,
+    cursor: i32,
+    default_attr: i32,
+    cells: Vec<i32>,
+    /// Emulated time until the cursor blinks.
+    blink_time: f64,
+    cursor_on: bool,
+    /// Screen has changed since it was last rendered.
+    dirty: bool,
+}
+
+impl Default for DevText {
+    fn default() -> Self {
+        DevText {
+            mode: MODE_80,
+            cursor: -1,
+            default_attr: DEFAULT_ATTR,
+            cells: vec![0; MAX_CELLS],
+            blink_time: BLINK_TIME,
+            cursor_on: true,
+            dirty: false,
+        }
+    }
+}
+
+impl DevText {
+    /// Advance emulated time. Blinks the cursor.
+    pub fn update(&mut self, delta_t: f64) {
+        self.blink_time -= delta_t;
+        if self.blink_time > 0. {
+            return;
+        }
+        self.blink_time = BLINK_TIME;
+        self.cursor_on = !self.cursor_on;
+        if self.cursor_visible() {
+            self.dirty = true;
+        }
+    }
+
+    /// Render the screen if it has changed since last call.
+    pub fn take_screen(&mut self) -> Option<TextScreen> {
+        if !std::mem::take(&mut self.dirty) {
+            return None;
+        }
+        Some(self.render())
+    }
+
+    fn columns(&self) -> usize {
+        match self.mode {
+            MODE_40 => 40,
+            _ => 80,
+        }
+    }
+
+    fn cursor_visible(&self) -> bool {
+        (0..(self.columns() * ROWS) as i32).contains(&self.cursor)
+    }
+
+    fn render(&self) -> TextScreen {
+        let columns = self.columns();
+        let width = columns * CELL_SIZE;
+        let height = ROWS * CELL_SIZE;
+        let mut pixels = vec![Rgba([0, 0, 0, 255]); width * height];
+        for (idx, cell) in self.cells[..columns * ROWS].iter().enumerate() {
+            let attr = match (cell >> 8) & 0xff {
+                0 => self.default_attr,
+                attr => attr,
+            };
+            let [r, g, b] = PALETTE[(attr & 0xf) as usize];
+            let fg = Rgba([r, g, b, 255]);
+            let [r, g, b] = PALETTE[((attr >> 4) & 0xf) as usize];
+            let bg = Rgba([r, g, b, 255]);
+            let mut glyph = *glyph(*cell as u8);
+            if self.cursor_on && idx as i32 == self.cursor {
+                // Underline cursor
+                glyph[CELL_SIZE - 2] = 0xff;
+                glyph[CELL_SIZE - 1] = 0xff;
+            }
+            let left = (idx % columns) * CELL_SIZE;
+            let top = (idx / columns) * CELL_SIZE;
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..CELL_SIZE {
+                    let color = if row >> x & 1 != 0 { fg } else { bg };
+                    pixels[(top + y) * width + left + x] = color;
+                }
+            }
+        }
+        TextScreen { width, height, pixels }
+    }
+
+    /// Get a mutable reference to memory at an address.
+    fn mem(&mut self, addr: usize) -> Option<&mut i32> {
+        match addr {
+            0x000 => Some(&mut self.mode),
+            0x001 => Some(&mut self.cursor),
+            0x002 => Some(&mut self.default_attr),
+            ADDR_CELLS..=0x8cf => Some(&mut self.cells[addr - ADDR_CELLS]),
+            _ => None,
+        }
+    }
+}
+
+impl Device for DevText {
+    fn reset(&mut self) {
+        *self = DevText::default();
+    }
+    fn on(&mut self) {}
+    fn off(&mut self) {
+        *self = DevText::default();
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl MMIO for DevText {
+    fn read(&mut self, addr: usize) -> Result<i32, ()> {
+        self.mem(addr).map(|value| *value).ok_or(())
+    }
+    fn write(&mut self, addr: usize, value: i32) -> Result<(), ()> {
+        if addr == 0x000 && value != MODE_80 && value != MODE_40 {
+            return Err(());
+        }
+        *self.mem(addr).ok_or(())? = value;
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dev_text() -> Result<(), ()> {
+        let mut text = DevText::default();
+
+        // Test wrong usage
+        assert!(text.read(0x003).is_err());
+        assert!(text.write(0x0ff, 0).is_err());
+        assert!(text.write(0x8d0, 0).is_err());
+        assert!(text.write(0x000, 2).is_err());
+
+        // Nothing written, nothing to show.
+        assert!(text.take_screen().is_none());
+
+        // 'A' with default attribute in top left, 'B' white on blue in second cell.
+        text.write(ADDR_CELLS, 'A' as i32)?;
+        text.write(ADDR_CELLS + 1, 0x1f00 | 'B' as i32)?;
+        assert_eq!(text.read(ADDR_CELLS + 1)?, 0x1f42);
+        let screen = text.take_screen().unwrap();
+        assert!(text.take_screen().is_none());
+        assert_eq!((screen.width, screen.height), (640, 200));
+        let gray = Rgba([0xaa, 0xaa, 0xaa, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([0xff, 0xff, 0xff, 255]);
+        let blue = Rgba([0x00, 0x00, 0xaa, 255]);
+        // 'A' top row is 0x0c: pixels 2 and 3 are set.
+        assert_eq!(screen.pixels[1], black);
+        assert_eq!(screen.pixels[2], gray);
+        // 'B' top row is 0x3f.
+        assert_eq!(screen.pixels[8], white);
+        assert_eq!(screen.pixels[8 + 7], blue);
+        // Second text row is blank.
+        assert_eq!(screen.pixels[8 * 640], black);
+
+        // 40 columns
+        text.write(0x000, MODE_40)?;
+        text.write(ADDR_CELLS + 40, 0x7000 | 'x' as i32)?;
+        let screen = text.take_screen().unwrap();
+        assert_eq!((screen.width, screen.height), (320, 200));
+        assert_eq!(screen.pixels[8 * 320], gray); // Gray background
+
+        // Cursor blinks
+        text.write(0x001, 41)?;
+        let screen = text.take_screen().unwrap();
+        assert_eq!(screen.pixels[15 * 320 + 8], gray);
+        text.update(BLINK_TIME);
+        let screen = text.take_screen().unwrap();
+        assert_eq!(screen.pixels[15 * 320 + 8], black);
+        // Hidden cursor doesn't cause redraws.
+        text.write(0x001, -1)?;
+        text.take_screen();
+        text.update(BLINK_TIME);
+        assert!(text.take_screen().is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+//!
+//! Serial port (UART)
+//!
+//! Byte-wide serial port with receive and transmit FIFOs. The other end is bridged to the host:
+//! - Loopback: Transmitted bytes are received back.
+//! - Listen: Wait for a TCP connection on a local address.
+//! - Connect: Connect to a TCP address. Reconnects if the connection is lost.
+//!
+//! Two titomachines can talk to each other by having one listen and the other connect. A host
+//! terminal works too, e.g. `nc 127.0.0.1 9191`. For a pseudo-terminal, bridge with socat:
+//! `socat pty,raw,echo=0 tcp:127.0.0.1:9191`.
+//!
+//! Transmission runs at 9600 baud in emulated time, so a byte is sent about every millisecond.
+//!
+//! Ports:
+//!  - Port 0: Data (global port 0x70)
+//!  - Port 1: Status (global port 0x71)
+//!
+//! Status bits:
+//! | Bit | Meaning                                   |
+//! | --- | ----------------------------------------- |
+//! | 0   | RX: received data available               |
+//! | 1   | TX full: transmit FIFO can't take more    |
+//! | 2   | TX empty: everything has been sent        |
+//! | 3   | Connected                                 |
+//!
+//! | Port | Read                                        | Write                                      |
+//! | ---- | ------------------------------------------- | ------------------------------------------ |
+//! | 0    | Next received byte, -1 if there's none      | Queue a byte (0-255) for transmission      |
+//! | 1    | Status                                      | Error                                      |
+//!
+//! Writing when the transmit FIFO is full is an error, and the byte is lost.
+//!
+//! Each received byte raises the UART interrupt (PIC bit 6, IVT entry 4), if it's enabled in the
+//! PIC.
+//!
+use super::{Device, PMIO};
+use crate::emulator::emu_debug::UartMode;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const FIFO_LEN: usize = 16;
+/// Time to transmit one byte: 10 bits at 9600 baud, in seconds of emulated time.
+const BYTE_TIME: f64 = 10. / 9600.;
+/// How often the bridge thread checks for data.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+const STATUS_RX: i32 = 0b_0001;
+const STATUS_TX_FULL: i32 = 0b_0010;
+const STATUS_TX_EMPTY: i32 = 0b_0100;
+const STATUS_CONNECTED: i32 = 0b_1000;
+
+/// Host side of the serial line. Runs in its own thread.
+struct Bridge {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    connected: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    /// Thread that owns the listening socket, in Listen mode.
+    listen_thread: Option<JoinHandle<()>>,
+}
+
+impl Bridge {
+    fn start(mode: &UartMode, addr: &str) -> Option<Self> {
+        let (tx_out, rx_out) = mpsc::channel();
+        let (tx_in, rx_in) = mpsc::channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let listener = match mode {
+            UartMode::Off | UartMode::Loopback => return None,
+            UartMode::Listen => match TcpListener::bind(addr).and_then(|l| l.set_nonblocking(true).map(|_| l)) {
+                Ok(listener) => Some(listener),
+                Err(e) => {
+                    println!("uart: couldn't listen on {}: {}", addr, e);
+                    return None;
+                }
+            },
+            UartMode::Connect => None,
+        };
+
+        let listening = listener.is_some();
+        let addr = addr.to_owned();
+        let thread_connected = connected.clone();
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                let stream = match &listener {
+                    Some(listener) => match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_) => {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    },
+                    None => match TcpStream::connect(&addr) {
+                        Ok(stream) => stream,
+                        Err(_) => {
+                            thread::sleep(RECONNECT_INTERVAL);
+                            continue;
+                        }
+                    },
+                };
+                thread_connected.store(true, Ordering::Relaxed);
+                let result = serve(stream, &tx_in, &rx_out, &thread_stop);
+                thread_connected.store(false, Ordering::Relaxed);
+                if result.is_err() {
+                    // Device is gone.
+                    return;
+                }
+            }
+        });
+
+        let listen_thread = listening.then_some(thread);
+        Some(Bridge { tx: tx_out, rx: rx_in, connected, stop, listen_thread })
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wait until the socket is closed, so the address can be listened on again right away.
+        // A connecting thread isn't waited for, as connect() can block for a long time.
+        if let Some(thread) = self.listen_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Move bytes between a connection and the device until either end goes away.
+/// Returns Err if the device end is gone.
+fn serve(mut stream: TcpStream, tx: &Sender<u8>, rx: &Receiver<u8>, stop: &AtomicBool) -> Result<(), ()> {
+    if stream.set_nonblocking(true).is_err() {
+        return Ok(());
+    }
+    let _ = stream.set_nodelay(true);
+    let mut buf = [0; 256];
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()), // Closed
+            Ok(n) => {
+                for byte in &buf[..n] {
+                    tx.send(*byte).map_err(|_| ())?;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(_) => return Ok(()),
+        }
+        let mut out = Vec::new();
+        loop {
+            match rx.try_recv() {
+                Ok(byte) => out.push(byte),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(()),
+            }
+        }
+        if !out.is_empty() {
+            let _ = stream.set_nonblocking(false);
+            if stream.write_all(&out).is_err() {
+                return Ok(());
+            }
+            let _ = stream.set_nonblocking(true);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Serial port
+pub(crate) struct DevUART {
+    mode: UartMode,
+    addr: String,
+    bridge: Option<Bridge>,
+    rx_fifo: VecDeque<u8>,
+    tx_fifo: VecDeque<u8>,
+    /// Emulated time until the byte being transmitted is done.
+    tx_time: f64,
+    /// Set when a byte is received. Bus passes this on to PIC.
+    pub(crate) interrupt: bool,
+}
+
+impl Default for DevUART {
+    fn default() -> Self {
+        DevUART {
+            mode: UartMode::Off,
+            addr: String::new(),
+            bridge: None,
+            rx_fifo: VecDeque::new(),
+            tx_fifo: VecDeque::new(),
+            tx_time: 0.,
+            interrupt: false,
+        }
+    }
+}
+
+impl DevUART {
+    /// Set what the serial line is connected to. Takes effect next time the machine is turned on.
+    pub fn set_bridge(&mut self, mode: UartMode, addr: String) {
+        self.mode = mode;
+        self.addr = addr;
+    }
+
+    /// Advance emulated time: transmit and receive.
+    pub fn update(&mut self, delta_t: f64) {
+        // Transmit
+        if !self.tx_fifo.is_empty() {
+            self.tx_time -= delta_t;
+            while self.tx_time <= 0. {
+                let Some(byte) = self.tx_fifo.pop_front() else {
+                    self.tx_time = 0.;
+                    break;
+                };
+                self.transmit(byte);
+                self.tx_time += BYTE_TIME;
+            }
+        }
+        // Receive
+        if let Some(bridge) = &self.bridge {
+            while self.rx_fifo.len() < FIFO_LEN {
+                let Ok(byte) = bridge.rx.try_recv() else {
+                    break;
+                };
+                self.rx_fifo.push_back(byte);
+                self.interrupt = true;
+            }
+        }
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        match self.mode {
+            UartMode::Loopback => {
+                if self.rx_fifo.len() < FIFO_LEN {
+                    self.rx_fifo.push_back(byte);
+                    self.interrupt = true;
+                }
+            }
+            _ => {
+                if let Some(bridge) = &self.bridge {
+                    let _ = bridge.tx.send(byte);
+                }
+            }
+        }
+    }
+
+    fn connected(&self) -> bool {
+        match &self.bridge {
+            Some(bridge) => bridge.connected.load(Ordering::Relaxed),
+            None => self.mode == UartMode::Loopback,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.bridge = None;
+        self.rx_fifo.clear();
+        self.tx_fifo.clear();
+        self.tx_time = 0.;
+        self.interrupt = false;
+    }
+}
+
+impl Device for DevUART {
+    fn reset(&mut self) {
+        self.clear();
+    }
+    fn on(&mut self) {
+        self.clear();
+        self.bridge = Bridge::start(&self.mode, &self.addr);
+    }
+    fn off(&mut self) {
+        self.clear();
+    }
+    fn set_pause(&mut self, _paused: bool) {}
+}
+
+impl PMIO for DevUART {
+    fn read_port(&mut self, port: u8) -> Result<i32, ()> {
+        match port {
+            0 => Ok(self.rx_fifo.pop_front().map(|byte| byte as i32).unwrap_or(-1)),
+            1 => {
+                let mut status = 0;
+                if !self.rx_fifo.is_empty() {
+                    status |= STATUS_RX;
+                }
+                if self.tx_fifo.len() >= FIFO_LEN {
+                    status |= STATUS_TX_FULL;
+                }
+                if self.tx_fifo.is_empty() {
+                    status |= STATUS_TX_EMPTY;
+                }
+                if self.connected() {
+                    status |= STATUS_CONNECTED;
+                }
+                Ok(status)
+            }
+            _ => Err(()),
+        }
+    }
+    fn write_port(&mut self, port: u8, value: i32) -> Result<(), ()> {
+        if port != 0 || self.tx_fifo.len() >= FIFO_LEN {
+            return Err(());
+        }
+        let byte = u8::try_from(value).map_err(|_| ())?;
+        if self.tx_fifo.is_empty() {
+            self.tx_time = BYTE_TIME;
+        }
+        self.tx_fifo.push_back(byte);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    /// Update the device until it has received something, or give up after a second.
+    fn wait_rx(uart: &mut DevUART) {
+        let start = Instant::now();
+        while uart.rx_fifo.is_empty() && start.elapsed() < Duration::from_secs(1) {
+            uart.update(BYTE_TIME);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn test_dev_uart() -> Result<(), ()> {
+        let mut uart = DevUART::default();
+        uart.on();
+
+        // Test wrong usage
+        assert!(uart.read_port(2).is_err());
+        assert!(uart.write_port(1, 0).is_err());
+        assert!(uart.write_port(0, 256).is_err());
+        assert!(uart.write_port(0, -1).is_err());
+
+        // Not connected: bytes go nowhere.
+        assert_eq!(uart.read_port(1)?, STATUS_TX_EMPTY);
+        assert_eq!(uart.read_port(0)?, -1);
+
+        // Loopback
+        uart.set_bridge(UartMode::Loopback, String::new());
+        uart.on();
+        assert_eq!(uart.read_port(1)?, STATUS_TX_EMPTY | STATUS_CONNECTED);
+        uart.write_port(0, 0x55)?;
+        uart.write_port(0, 0)?;
+        assert_eq!(uart.read_port(1)?, STATUS_CONNECTED);
+        uart.update(BYTE_TIME);
+        assert!(uart.interrupt);
+        assert_eq!(uart.read_port(1)?, STATUS_RX | STATUS_CONNECTED);
+        uart.update(BYTE_TIME);
+        assert_eq!(uart.read_port(1)?, STATUS_RX | STATUS_TX_EMPTY | STATUS_CONNECTED);
+        assert_eq!(uart.read_port(0)?, 0x55);
+        assert_eq!(uart.read_port(0)?, 0);
+        assert_eq!(uart.read_port(0)?, -1);
+
+        // TX FIFO fills up
+        for i in 0..FIFO_LEN as i32 {
+            uart.write_port(0, i)?;
+        }
+        assert!(uart.read_port(1)? & STATUS_TX_FULL != 0);
+        assert!(uart.write_port(0, 0).is_err());
+        uart.off();
+
+        // TCP, with a host listener on loopback.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        uart.set_bridge(UartMode::Connect, addr);
+        uart.on();
+        let (mut host, _) = listener.accept().unwrap();
+        host.write_all(b"hi").unwrap();
+        wait_rx(&mut uart);
+        assert!(uart.read_port(1)? & STATUS_CONNECTED != 0);
+        assert_eq!(uart.read_port(0)?, 'h' as i32);
+        wait_rx(&mut uart);
+        assert_eq!(uart.read_port(0)?, 'i' as i32);
+
+        uart.write_port(0, '!' as i32)?;
+        uart.update(BYTE_TIME);
+        let mut buf = [0; 1];
+        host.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        host.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"!");
+        uart.off();
+
+        // Listen, on an address that is free again after a power cycle.
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        uart.set_bridge(UartMode::Listen, addr.clone());
+        uart.on();
+        uart.off();
+        uart.on();
+        let mut host = TcpStream::connect(&addr).unwrap();
+        host.write_all(b"ok").unwrap();
+        wait_rx(&mut uart);
+        assert!(uart.read_port(1)? & STATUS_CONNECTED != 0);
+        assert_eq!(uart.read_port(0)?, 'o' as i32);
+        uart.off();
+
+        Ok(())
+    }
+}
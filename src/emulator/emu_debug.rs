@@ -5,6 +5,8 @@
 
 use super::Emu;
 use std::ops::Range;
+use std::path::PathBuf;
+use image::Rgba;
 use libttktk::b91::B91;
 
 pub enum CtrlMSG {
@@ -17,19 +19,105 @@ pub enum CtrlMSG {
     ClearMem,
     SetRate(f32),
     SetTurbo(bool),
+    SetStdinPath(Option<PathBuf>),
+    SetStdoutPath(Option<PathBuf>),
+    SetMidiRecordPath(Option<PathBuf>),
+    SetPsgRecordPath(Option<PathBuf>),
+    /// Host mute and solo masks for PSG channels
+    SetPsgMixer(i32, i32),
+    SetDiskImagePath(Option<PathBuf>),
+    SetPrinterPath(Option<PathBuf>),
+    SetUartBridge(UartMode, String),
+    SetRngSeed(Option<i32>),
+    SetRefreshRate(f32),
+    // Input devices
+    SetPadButtons(i32),
+    RawKbdChar(i32),
+    RawKbdKey(i32, bool),
+    SetMouse(i32, i32, i32),
     GetState,
     GetMem(Range<u32>),
+    /// Read a word as it's seen on screen: framebuffer addresses read the shown page, not the
+    /// draw page.
+    GetShownWord(u32),
     EnableBreakpoints(bool),
     ClearBreakpoints,
     InsertBreakpoint(usize),
     RemoveBreakpoint(usize),
+    /// Pause when the program writes to an address.
+    InsertWatchpoint(u32),
+    RemoveWatchpoint(u32),
+    ClearWatchpoints,
 }
 
 pub enum ReplyMSG {
     State(EmuState),
     Regs(DebugRegs),
     Mem(Vec<i32>),
+    /// Address and value
+    Word(u32, i32),
     SegmentOffsets(usize, usize, usize),
+    PrinterOutput(String),
+    TextScreen(TextScreen),
+    PsgState(PsgState),
+}
+
+/// What the serial port is connected to on the host side.
+#[derive(Clone, Copy, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+pub enum UartMode {
+    #[default]
+    Off,
+    Loopback,
+    /// Wait for a TCP connection
+    Listen,
+    /// Connect to a TCP address
+    Connect,
+}
+
+/// Names of the PSG channels, by channel number.
+pub const PSG_CHANNEL_NAMES: [&str; 5] = ["Pulse 0", "Pulse 1", "Ramp", "Noise", "PCM"];
+/// PSG status register bit: channel is making sound.
+pub const PSG_STATUS_ACTIVE: i32 = 1;
+
+/// PSG state for the mixer and channel panels.
+pub struct PsgState {
+    /// Pan of each channel. -128 left, 0 center, 128 right.
+    pub pan: Vec<i32>,
+    /// Master volume 0-255
+    pub master: i32,
+    /// Bit n mutes channel n.
+    pub mute: i32,
+    /// Bit n solos channel n.
+    pub solo: i32,
+    pub channels: Vec<PsgChannelState>,
+}
+
+/// State of one PSG channel. Values are as written to the registers.
+pub struct PsgChannelState {
+    /// MIDI key
+    pub note: i32,
+    /// Frequency the note plays at, in Hz
+    pub freq: f32,
+    pub vol: i32,
+    /// Pulse width / duty cycle, or playback rate for the PCM channel.
+    pub pw: i32,
+    /// Names of envelope flags that are set
+    pub env_flags: Vec<&'static str>,
+    /// ADSR stage, if in ADSR mode
+    pub env_stage: Option<&'static str>,
+    /// Envelope position 0-255
+    pub env_position: i32,
+    /// Status register
+    pub status: i32,
+    /// Latest output samples, oldest first.
+    pub waveform: Vec<f32>,
+}
+
+/// Rendered image of the text mode display.
+pub struct TextScreen {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgba<u8>>,
 }
 
 pub struct EmuState {
@@ -85,13 +173,32 @@ impl Emu {
                     // Settings
                     CtrlMSG::SetRate(rate) => self.tick_rate = rate,
                     CtrlMSG::SetTurbo(t) => self.turbo = t,
+                    CtrlMSG::SetStdinPath(path) => self.bus.stdio.set_stdin_path(path),
+                    CtrlMSG::SetStdoutPath(path) => self.bus.stdio.set_stdout_path(path),
+                    CtrlMSG::SetMidiRecordPath(path) => self.bus.midi.set_record_path(path),
+                    CtrlMSG::SetPsgRecordPath(path) => self.bus.psg.set_record_path(path),
+                    CtrlMSG::SetPsgMixer(mute, solo) => self.bus.psg.set_host_mixer(mute, solo),
+                    CtrlMSG::SetDiskImagePath(path) => self.bus.disk.set_image_path(path),
+                    CtrlMSG::SetPrinterPath(path) => self.bus.printer.set_output_path(path),
+                    CtrlMSG::SetUartBridge(mode, addr) => self.bus.uart.set_bridge(mode, addr),
+                    CtrlMSG::SetRngSeed(seed) => self.bus.rng.set_seed(seed),
+                    CtrlMSG::SetRefreshRate(rate) => self.bus.display.set_refresh_rate(rate),
+                    // Input devices
+                    CtrlMSG::SetPadButtons(buttons) => self.bus.pad.set_buttons(buttons),
+                    CtrlMSG::RawKbdChar(c) => self.bus.rawkbd.push_char(c),
+                    CtrlMSG::RawKbdKey(code, pressed) => self.bus.rawkbd.push_key(code, pressed),
+                    CtrlMSG::SetMouse(x, y, buttons) => self.bus.mouse.set_state(x, y, buttons),
                     // Debug
                     CtrlMSG::GetState => self.debug_sendstate(),
                     CtrlMSG::GetMem(range) => self.debug_sendmem(range),
+                    CtrlMSG::GetShownWord(addr) => self.debug_sendword(addr),
                     CtrlMSG::EnableBreakpoints(enable) => self.breakpoints_enabled = enable,
                     CtrlMSG::ClearBreakpoints => self.breakpoints.clear(),
                     CtrlMSG::InsertBreakpoint(addr) => { self.breakpoints.insert(addr); }
                     CtrlMSG::RemoveBreakpoint(addr) => { self.breakpoints.remove(&addr); }
+                    CtrlMSG::InsertWatchpoint(addr) => { self.bus.watchpoints.insert(addr); }
+                    CtrlMSG::RemoveWatchpoint(addr) => { self.bus.watchpoints.remove(&addr); }
+                    CtrlMSG::ClearWatchpoints => self.bus.watchpoints.clear(),
                 }
             } else {
                 break;
@@ -125,6 +232,12 @@ impl Emu {
         let _ = self.tx.send(ReplyMSG::Mem(retvec));
     }
 
+    pub fn debug_sendword(&mut self, addr: u32) {
+        if let Ok(value) = self.bus.read_shown(addr) {
+            let _ = self.tx.send(ReplyMSG::Word(addr, value));
+        }
+    }
+
     fn debug_sendregs(&mut self) {
         let cu = self.cpu.debug_get_cu();
         let mmu = self.cpu.debug_get_mmu();
@@ -20,6 +20,95 @@ fn test_cpu_mmio() {
     assert_eq!(cpu.debug_get_gpr(2), expected);
 }
 
+#[test]
+/// Tests blitter commands on RAM and display memory
+fn test_bus_blitter() {
+    let mut bus = Bus::new();
+    bus.turn_on();
+    // 2x2 sprite in RAM, with 0 as the transparent color.
+    for (addr, value) in [(0x100, 0x0f00), (0x101, 0), (0x102, 0), (0x103, 0x00f0)] {
+        bus.write(addr, value).unwrap();
+    }
+    let set = |bus: &mut Bus, regs: [i32; 7]| {
+        for (port, value) in regs.into_iter().enumerate() {
+            bus.write_port(0x91 + port as i32, value).unwrap();
+        }
+    };
+
+    // Fill a 3x2 rectangle at (1, 1) on the display.
+    set(&mut bus, [0, 0x2000 + 161, 3, 2, 0, 160, 0x000f]);
+    bus.write_port(0x90, 1).unwrap();
+    assert_eq!(bus.read(0x2000 + 160).unwrap(), 0);
+    assert_eq!(bus.read(0x2000 + 161).unwrap(), 0x000f);
+    assert_eq!(bus.read(0x2000 + 323).unwrap(), 0x000f);
+    assert_eq!(bus.read(0x2000 + 324).unwrap(), 0);
+    bus.advance_time(0.001);
+    assert_eq!(bus.read_port(0x90).unwrap(), 0);
+
+    // Masked copy of the sprite over the rectangle.
+    set(&mut bus, [0x100, 0x2000 + 161, 2, 2, 2, 160, 0]);
+    bus.write_port(0x90, 3).unwrap();
+    assert_eq!(bus.read(0x2000 + 161).unwrap(), 0x0f00);
+    assert_eq!(bus.read(0x2000 + 162).unwrap(), 0x000f);
+    assert_eq!(bus.read(0x2000 + 321).unwrap(), 0x000f);
+    assert_eq!(bus.read(0x2000 + 322).unwrap(), 0x00f0);
+    bus.advance_time(0.001);
+
+    // Overlapping copy shifts a row right by one.
+    set(&mut bus, [0x100, 0x101, 3, 1, 0, 0, 0]);
+    bus.write_port(0x90, 2).unwrap();
+    assert_eq!(bus.read(0x101).unwrap(), 0x0f00);
+    assert_eq!(bus.read(0x103).unwrap(), 0);
+    bus.advance_time(0.001);
+
+    // Running off the end of memory stops with an error.
+    set(&mut bus, [0, 0xfffe, 4, 1, 0, 0, 0]);
+    bus.write_port(0x90, 1).unwrap();
+    assert_eq!(bus.read_port(0x90).unwrap(), 0b_11);
+}
+
+#[test]
+/// Tests that writing a watched address is noticed, also when the blitter writes it
+fn test_bus_watchpoint() {
+    let mut bus = Bus::new();
+    bus.watchpoints.insert(0x2000 + 161);
+    bus.write(0x2000 + 160, 1).unwrap();
+    assert!(!bus.watch_hit);
+    bus.write(0x2000 + 161, 1).unwrap();
+    assert!(std::mem::take(&mut bus.watch_hit));
+
+    // Fill 2x2 at (0, 0)
+    for (port, value) in [(0x92, 0x2000), (0x93, 2), (0x94, 2), (0x96, 160)] {
+        bus.write_port(port, value).unwrap();
+    }
+    bus.write_port(0x90, 1).unwrap();
+    assert!(bus.watch_hit);
+}
+
+#[test]
+/// Tests that triggering the PSG's PCM channel plays samples copied from memory
+fn test_bus_psg_pcm() {
+    let mut bus = Bus::new();
+    bus.write(0x100, 0x00407f40).unwrap();
+    bus.write(0x101, 0x00c081c0).unwrap();
+    // One-shot, 8000 samples per second, full volume.
+    for (addr, value) in [(0x6b41, 255), (0x6b42, 8000), (0x6b47, 0x100), (0x6b48, 6), (0x6b49, -1)] {
+        bus.write(addr, value).unwrap();
+    }
+    bus.write(0x6b4a, 1).unwrap();
+    assert_eq!(bus.read(0x6b46).unwrap() & 1, 1);
+    // Samples are copied, so changing memory doesn't matter.
+    bus.write(0x100, 0).unwrap();
+    bus.advance_time(0.0005);
+    assert_eq!(bus.read(0x6b46).unwrap() & 1, 1);
+    bus.advance_time(0.001);
+    assert_eq!(bus.read(0x6b46).unwrap() & 1, 0);
+
+    // Samples past the end of memory can't be loaded.
+    bus.write(0x6b47, 0xfffe).unwrap();
+    assert!(bus.write(0x6b4a, 1).is_err());
+}
+
 /*
 TODO: These tests already needed rewrite in a smaller scope as they rely on compiler and loader,
  but now that loader's gone, they're just out.
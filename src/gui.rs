@@ -1,7 +1,9 @@
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use eframe::emath::format_with_decimals_in_range;
 use eframe::epaint::FontId;
-use crate::{emulator::emu_debug::CtrlMSG, TitoApp};
+use crate::{emulator::emu_debug::{CtrlMSG, UartMode}, TitoApp};
+use rfd::FileDialog;
 use serde;
 
 pub mod gui_editor;
@@ -9,6 +11,10 @@ pub(crate) mod memoryview;
 pub(crate) mod cpuview;
 pub(crate) mod graphicsview;
 pub(crate) mod legacytermview;
+pub(crate) mod mixerview;
+pub(crate) mod printerview;
+pub(crate) mod psgview;
+pub(crate) mod textview;
 mod emutoolbar;
 
 use egui::{Align, Button, Color32, Context, DragValue, Frame, Layout, Modifiers, OpenUrl, RichText, TopBottomPanel, Ui};
@@ -50,6 +56,8 @@ impl Radix {
 const URL_GITHUB: &str = "https://github.com/sevonj/titomachine/";
 const URL_GUIDE: &str = "https://sevonj.github.io/titouserdoc/";
 const URL_OLDREF: &str = "https://www.cs.helsinki.fi/group/titokone/ttk91_ref_fi.html";
+/// Size of new disk images in bytes. 2048 sectors of 512 bytes.
+const BLANK_DISK_SIZE: usize = 1024 * 1024;
 const FONT_TBL: FontId = FontId::monospace(12.0);
 const FONT_TBLH: FontId = FontId::proportional(12.5);
 const COL_TEXT: Color32 = Color32::DARK_GRAY;
@@ -249,6 +257,9 @@ impl TitoApp {
             if ui.checkbox(&mut self.emu_turbo, "Turbo Mode").changed() {
                 let _ = self.tx_ctrl.send(CtrlMSG::SetTurbo(self.emu_turbo));
             };
+            ui.menu_button("Devices", |ui| {
+                self.gui_device_settings(ui);
+            });
 
             ui.menu_button("Language", |ui| {
                 ui.add_enabled_ui(false, |ui| {
@@ -281,6 +292,153 @@ impl TitoApp {
         });
     }
 
+    /// Device options submenu.
+    fn gui_device_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label("=STDIN file");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_stdin_path);
+            if ui.button(name).on_hover_text("Select input file").clicked() {
+                if let Some(path) = FileDialog::new().set_directory(&self.config.workdir).pick_file() {
+                    self.config.dev_stdin_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("✖").on_hover_text("Clear").clicked() {
+                self.config.dev_stdin_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("=STDOUT file");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_stdout_path);
+            if ui.button(name).on_hover_text("Select output file").clicked() {
+                if let Some(path) = FileDialog::new().set_directory(&self.config.workdir).save_file() {
+                    self.config.dev_stdout_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("✖").on_hover_text("Clear").clicked() {
+                self.config.dev_stdout_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("MIDI recording file");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_midi_record_path);
+            if ui.button(name).on_hover_text("Select .mid file to record into").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .set_directory(&self.config.workdir)
+                    .add_filter("MIDI", &["mid"])
+                    .save_file()
+                {
+                    self.config.dev_midi_record_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("✖").on_hover_text("Don't record").clicked() {
+                self.config.dev_midi_record_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("PSG recording file");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_psg_record_path);
+            if ui.button(name).on_hover_text("Select .wav file to record into").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .set_directory(&self.config.workdir)
+                    .add_filter("WAV", &["wav"])
+                    .save_file()
+                {
+                    self.config.dev_psg_record_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("✖").on_hover_text("Don't record").clicked() {
+                self.config.dev_psg_record_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("Disk image");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_disk_image_path);
+            if ui.button(name).on_hover_text("Select disk image").clicked() {
+                if let Some(path) = FileDialog::new().set_directory(&self.config.workdir).pick_file() {
+                    self.config.dev_disk_image_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("New").on_hover_text("Create a blank disk image").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .set_directory(&self.config.workdir)
+                    .add_filter("Disk image", &["img"])
+                    .save_file()
+                {
+                    match std::fs::write(&path, vec![0; BLANK_DISK_SIZE]) {
+                        Ok(_) => {
+                            self.config.dev_disk_image_path = Some(path);
+                            self.send_device_settings();
+                        }
+                        Err(e) => println!("Couldn't create disk image: {}", e),
+                    }
+                }
+            }
+            if ui.button("✖").on_hover_text("Eject").clicked() {
+                self.config.dev_disk_image_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("Printer file");
+        ui.horizontal(|ui| {
+            let name = path_display_name(&self.config.dev_printer_path);
+            if ui.button(name).on_hover_text("Select text file to print into").clicked() {
+                if let Some(path) = FileDialog::new().set_directory(&self.config.workdir).save_file() {
+                    self.config.dev_printer_path = Some(path);
+                    self.send_device_settings();
+                }
+            }
+            if ui.button("✖").on_hover_text("Only print into the Printer panel").clicked() {
+                self.config.dev_printer_path = None;
+                self.send_device_settings();
+            }
+        });
+        ui.label("Serial port");
+        ui.horizontal(|ui| {
+            let mut changed = false;
+            changed |= ui.radio_value(&mut self.config.dev_uart_mode, UartMode::Off, "Off").changed();
+            changed |= ui.radio_value(&mut self.config.dev_uart_mode, UartMode::Loopback, "Loopback").changed();
+            changed |= ui.radio_value(&mut self.config.dev_uart_mode, UartMode::Listen, "Listen").changed();
+            changed |= ui.radio_value(&mut self.config.dev_uart_mode, UartMode::Connect, "Connect").changed();
+            if changed {
+                self.send_device_settings();
+            }
+        });
+        let addr_edit = ui.text_edit_singleline(&mut self.config.dev_uart_addr)
+            .on_hover_text("TCP address to listen on, or connect to");
+        if addr_edit.changed() {
+            self.send_device_settings();
+        }
+        ui.label("Display refresh rate");
+        let rate_edit = ui.add(DragValue::new(&mut self.config.dev_display_refresh_rate)
+            .clamp_range(1..=240)
+            .suffix(" Hz"));
+        if rate_edit.changed() {
+            self.send_device_settings();
+        }
+        ui.label("Random number generator");
+        ui.horizontal(|ui| {
+            let mut changed = ui.checkbox(&mut self.config.dev_rng_deterministic, "Fixed seed")
+                .on_hover_text("Same random numbers on every run")
+                .changed();
+            ui.add_enabled_ui(self.config.dev_rng_deterministic, |ui| {
+                changed |= ui.add(DragValue::new(&mut self.config.dev_rng_seed)).changed();
+            });
+            if changed {
+                self.send_device_settings();
+            }
+        });
+        ui.label("Changes take effect when the machine is turned on.");
+    }
+
     fn consume_shortcuts(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         if ui.input_mut(|i| i.consume_shortcut(&SHORTCUT_DEBUG_GUI)) {
             let debug = ui.style().debug.debug_on_hover;
@@ -323,6 +481,7 @@ impl TitoApp {
                         let _ = self.tx_ctrl.send(CtrlMSG::PlaybackStop);
                     }
                     false => {
+                        self.send_device_settings();
                         let _ = self.tx_ctrl.send(CtrlMSG::PlaybackStart);
                     }
                 }
@@ -380,12 +539,28 @@ impl TitoApp {
                 .frame(Frame::none())
                 .show(ctx, |ui| {
                     self.graphicsview.ui(ui, &mut self.config, &self.tx_ctrl);
+                    if let Some(addr) = self.graphicsview.take_memview_jump() {
+                        self.config.memview_visible = true;
+                        self.config.memview_follow_pc = false;
+                        self.memoryview.jump_to(addr as usize);
+                    }
+                    self.textview.ui(ui, &mut self.config, &self.tx_ctrl);
+                    self.printerview.ui(ui, &mut self.config, &self.tx_ctrl);
+                    self.mixerview.ui(ui, &mut self.config, &self.tx_ctrl);
+                    self.psgview.ui(ui, &mut self.config, &self.tx_ctrl);
                     self.memoryview.ui(ui, &mut self.config, &self.tx_ctrl);
                 });
         });
     }
 }
 
+/// Short name of an optional file path for buttons and labels.
+fn path_display_name(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => path.file_name().unwrap_or_default().to_string_lossy().into(),
+        None => "(none)".into(),
+    }
+}
 
 /// Trait for emulator GUI panels
 pub trait EmulatorPanel {
@@ -25,6 +25,7 @@ impl TitoApp {
             } else {
                 self.emu_running = true;
                 let _ = self.tx_ctrl.send(CtrlMSG::EnableBreakpoints(self.config.memview_breakpoints_enabled));
+                self.send_device_settings();
                 let _ = self.tx_ctrl.send(CtrlMSG::PlaybackStart);
             }
         }
@@ -58,6 +59,8 @@ impl TitoApp {
             let _ = self.tx_ctrl.send(CtrlMSG::Reset());
             self.legacytermview.clear();
             self.graphicsview.clear();
+            self.printerview.clear();
+            self.textview.clear();
         }
         ui.separator();
     }
@@ -5,49 +5,406 @@
 //! This module houses the Graphics Display Panel
 //!
 
+use std::collections::HashSet;
 use std::sync::mpsc::{Receiver, Sender};
-use egui::{TopBottomPanel, Ui, Layout, Button};
-use egui_extras::RetainedImage;
-use image::{ImageBuffer, Rgba};
+use rfd::FileDialog;
+use egui::{TopBottomPanel, Ui, Layout, Button, ColorImage, Event, EventFilter, Grid, Key, Modifiers, PointerButton,
+           Pos2, Rect, Response, Sense, TextureHandle, TextureOptions, Vec2};
+use image::{Rgba, RgbaImage};
 use num_traits::clamp;
 use crate::config::Config;
 use crate::emulator::emu_debug::CtrlMSG;
 use crate::gui::EmulatorPanel;
 
+mod recording;
+
+use self::recording::{RecordFormat, Recording};
+
+/// Gamepad buttons in the same order as the device's button bits.
+const PAD_BUTTON_NAMES: [&str; 8] = ["Up", "Down", "Left", "Right", "A", "B", "Select", "Start"];
+
+/// How the display image is scaled to the panel.
+#[derive(Clone, Copy, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+pub enum DisplayScale {
+    /// As large as fits
+    #[default]
+    Fit,
+    /// Largest whole multiple of the display resolution that fits, so all pixels are the same size
+    Integer,
+}
 
 pub(crate) struct GraphicsView {
-    rx: Receiver<Vec<Rgba<u8>>>,
-    framebuffer: Vec<Rgba<u8>>,
-    displaybuf: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
-    image: Option<RetainedImage>,
+    /// Frames from the emulator, with their frame numbers
+    rx: Receiver<(i32, RgbaImage)>,
+    framebuffer: RgbaImage,
+    /// Framebuffer has changed since it was uploaded into the texture.
+    framebuffer_dirty: bool,
+    texture: Option<TextureHandle>,
+    /// Gamepad state that was last sent to the emulator
+    pad_buttons: i32,
+    /// Gamepad button that is waiting for a new key binding
+    pad_rebind: Option<usize>,
+    /// Modifier keys that were last sent to the raw keyboard
+    kbd_modifiers: Modifiers,
+    /// Mouse state that was last sent to the emulator: x, y, buttons
+    mouse_state: (i32, i32, i32),
+    recording: Option<Recording>,
+    /// Latest value of an inspected pixel's address: address, value
+    inspected_value: Option<(u32, i32)>,
+    /// Addresses with a write watchpoint set from the pixel inspector
+    watchpoints: HashSet<u32>,
+    /// Address the Memory Explorer should show
+    memview_jump: Option<u32>,
 }
 
 impl GraphicsView {
-    pub fn new(rx: Receiver<Vec<Rgba<u8>>>) -> Self {
+    pub fn new(rx: Receiver<(i32, RgbaImage)>) -> Self {
         Self {
             rx,
-            framebuffer: vec![image::Rgba([0, 0, 0, 255, ]); 120 * 160],
-            displaybuf: None,
-            image: None,
+            framebuffer: blank_frame(),
+            framebuffer_dirty: true,
+            texture: None,
+            pad_buttons: 0,
+            pad_rebind: None,
+            kbd_modifiers: Modifiers::NONE,
+            mouse_state: (-1, -1, 0),
+            recording: None,
+            inspected_value: None,
+            watchpoints: HashSet::new(),
+            memview_jump: None,
         }
     }
 
     /// Clear framebuffer with black.
     pub fn clear(&mut self) {
-        self.framebuffer = vec![image::Rgba([0, 0, 0, 255, ]); 120 * 160];
+        self.framebuffer = blank_frame();
+        self.framebuffer_dirty = true;
+    }
+
+    /// Emulator's reply to a pixel inspector query.
+    pub fn set_inspected_value(&mut self, addr: u32, value: i32) {
+        self.inspected_value = Some((addr, value));
+    }
+
+    /// Address that was clicked in the pixel inspector, if any.
+    pub fn take_memview_jump(&mut self) -> Option<u32> {
+        self.memview_jump.take()
     }
 
     /// Try to receive a new framebuffer.
     fn update(&mut self) {
-        while let Ok(vec) = self.rx.try_recv() {
-            self.framebuffer = vec;
+        while let Ok((number, frame)) = self.rx.try_recv() {
+            if let Some(recording) = &mut self.recording {
+                recording.push(number, &frame);
+            }
+            self.framebuffer = frame;
+            self.framebuffer_dirty = true;
+        }
+    }
+
+    /// Upload framebuffer into the texture if it has changed.
+    fn update_texture(&mut self, ui: &Ui) {
+        if !std::mem::take(&mut self.framebuffer_dirty) {
+            return;
+        }
+        let (w, h) = self.framebuffer.dimensions();
+        let image = ColorImage::from_rgba_unmultiplied([w as usize, h as usize], self.framebuffer.as_raw());
+        match &mut self.texture {
+            Some(texture) => texture.set(image, TextureOptions::NEAREST),
+            None => self.texture = Some(ui.ctx().load_texture("display", image, TextureOptions::NEAREST)),
+        }
+    }
+
+    /// Save current frame as a PNG file.
+    fn screenshot(&self, config: &Config) {
+        let Some(path) = FileDialog::new()
+            .set_directory(&config.workdir)
+            .add_filter("PNG", &["png"])
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(e) = self.framebuffer.save_with_format(&path, image::ImageFormat::Png) {
+            println!("Couldn't save screenshot: {}", e);
+        }
+    }
+
+    /// Ask where to record, and start recording.
+    fn start_recording(&mut self, config: &Config, format: RecordFormat) {
+        let dialog = FileDialog::new().set_directory(&config.workdir);
+        let path = match format {
+            RecordFormat::Gif => dialog.add_filter("GIF", &["gif"]).save_file(),
+            RecordFormat::PngSequence => dialog.set_title("Folder for PNG frames").pick_folder(),
+        };
+        if let Some(path) = path {
+            self.recording = Some(Recording::start(format, path, config.dev_display_refresh_rate));
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(Err(e)) = self.recording.take().map(Recording::stop) {
+            println!("Recording failed: {}", e);
+        }
+    }
+
+    /// Screenshot and recording controls
+    fn capture_controls(&mut self, ui: &mut Ui, config: &Config) {
+        if ui.button("📷").on_hover_text("Save screenshot").clicked() {
+            self.screenshot(config);
+        }
+        match &self.recording {
+            Some(recording) => {
+                let text = format!("⏹ Stop ({} frames)", recording.frames());
+                if ui.button(text).clicked() {
+                    self.stop_recording();
+                }
+            }
+            None => {
+                ui.menu_button("⏺ Record", |ui| {
+                    if ui.button("GIF...").clicked() {
+                        ui.close_menu();
+                        self.start_recording(config, RecordFormat::Gif);
+                    }
+                    if ui.button("PNG sequence...").clicked() {
+                        ui.close_menu();
+                        self.start_recording(config, RecordFormat::PngSequence);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Clicking the display gives it keyboard focus. While focused, host keys control the gamepad
+    /// and the raw keyboard. Mouse works regardless of focus.
+    fn handle_input(&mut self, ui: &mut Ui, config: &Config, sender: &Sender<CtrlMSG>, response: &Response) {
+        let response = ui.interact(response.rect, ui.id().with("display_input"), Sense::click_and_drag());
+        if response.clicked() {
+            response.request_focus();
+        }
+        let focused = response.has_focus();
+        if focused {
+            // Keep arrow keys and tab from moving focus to other widgets.
+            ui.memory_mut(|mem| mem.set_focus_lock_filter(response.id, EventFilter {
+                tab: true,
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                ..Default::default()
+            }));
+            ui.painter().rect_stroke(response.rect, 0.0, ui.visuals().selection.stroke);
+        }
+        self.update_pad(ui, config, sender, focused);
+        self.update_rawkbd(ui, sender, focused);
+        self.update_mouse(ui, sender, Some(&response));
+    }
+
+    /// Send mouse state to the emulator if it has changed. None means the display isn't visible.
+    fn update_mouse(&mut self, ui: &Ui, sender: &Sender<CtrlMSG>, response: Option<&Response>) {
+        let mut state = (-1, -1, 0);
+        if let Some(response) = response {
+            // Keep tracking outside the display while dragging.
+            let pos = match response.dragged() {
+                true => ui.input(|i| i.pointer.interact_pos()),
+                false => response.hover_pos(),
+            };
+            if let Some(pos) = pos {
+                let (x, y) = self.pixel_at(pos, response.rect);
+                let buttons = ui.input(|i| {
+                    [PointerButton::Primary, PointerButton::Secondary, PointerButton::Middle]
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, button)| i.pointer.button_down(**button))
+                        .fold(0, |acc, (bit, _)| acc | 1 << bit)
+                });
+                state = (x, y, buttons);
+            }
+        }
+        if state != self.mouse_state {
+            self.mouse_state = state;
+            let _ = sender.send(CtrlMSG::SetMouse(state.0, state.1, state.2));
+        }
+    }
+
+    /// Display pixel under a point on the display image. Points outside are clamped to the edges.
+    fn pixel_at(&self, pos: Pos2, rect: Rect) -> (i32, i32) {
+        let rel = (pos - rect.min) / rect.size();
+        let (frame_w, frame_h) = self.framebuffer.dimensions();
+        let x = clamp((rel.x * frame_w as f32) as i32, 0, frame_w as i32 - 1);
+        let y = clamp((rel.y * frame_h as f32) as i32, 0, frame_h as i32 - 1);
+        (x, y)
+    }
+
+    /// Memory address of a display pixel. In 320x240 mode, four pixels share a word.
+    fn pixel_address(&self, x: i32, y: i32) -> u32 {
+        let frame_w = self.framebuffer.width() as i32;
+        let pixels_per_word = match frame_w {
+            320 => 4,
+            _ => 1,
+        };
+        0x2000 + ((y * frame_w + x) / pixels_per_word) as u32
+    }
+
+    /// Pixel inspector: hovering shows the pixel's address and value. Click shows the address in
+    /// Memory Explorer, right click toggles a write watchpoint on it.
+    fn inspect(&mut self, ui: &Ui, config: &Config, sender: &Sender<CtrlMSG>, response: &Response) {
+        let response = ui.interact(response.rect, ui.id().with("display_inspect"), Sense::click());
+        let Some(pos) = response.hover_pos() else {
+            return;
+        };
+        let (x, y) = self.pixel_at(pos, response.rect);
+        let addr = self.pixel_address(x, y);
+        let _ = sender.send(CtrlMSG::GetShownWord(addr));
+
+        if response.clicked() {
+            self.memview_jump = Some(addr);
+        }
+        if response.secondary_clicked() {
+            match self.watchpoints.remove(&addr) {
+                true => {
+                    let _ = sender.send(CtrlMSG::RemoveWatchpoint(addr));
+                }
+                false => {
+                    self.watchpoints.insert(addr);
+                    let _ = sender.send(CtrlMSG::InsertWatchpoint(addr));
+                }
+            }
         }
+
+        let value = match self.inspected_value {
+            Some((value_addr, value)) if value_addr == addr => config.memview_value_base.format_i32(value),
+            _ => "...".into(),
+        };
+        let watched = self.watchpoints.contains(&addr);
+        response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("x: {x}, y: {y}"));
+            ui.label(format!("Address: {}", config.memview_addr_base.format_addr(addr as usize)));
+            ui.label(format!("Value: {value}"));
+            if watched {
+                ui.label("Write watchpoint set");
+            }
+        });
+    }
+
+    /// Send gamepad state to the emulator if it has changed.
+    fn update_pad(&mut self, ui: &Ui, config: &Config, sender: &Sender<CtrlMSG>, focused: bool) {
+        let mut buttons = 0;
+        if focused && self.pad_rebind.is_none() {
+            ui.input(|i| {
+                for (bit, key) in config.pad_bindings.iter().enumerate() {
+                    if i.key_down(*key) {
+                        buttons |= 1 << bit;
+                    }
+                }
+            });
+        }
+        if buttons != self.pad_buttons {
+            self.pad_buttons = buttons;
+            let _ = sender.send(CtrlMSG::SetPadButtons(buttons));
+        }
+    }
+
+    /// Send keyboard events to the raw keyboard device.
+    fn update_rawkbd(&mut self, ui: &Ui, sender: &Sender<CtrlMSG>, focused: bool) {
+        if !focused || self.pad_rebind.is_some() {
+            // Release held modifiers when focus is lost.
+            self.send_modifiers(Modifiers::NONE, sender);
+            return;
+        }
+        ui.input(|i| {
+            for event in &i.events {
+                match event {
+                    Event::Key { key, pressed, repeat, .. } => {
+                        if let (false, Some(code)) = (repeat, key_code(*key)) {
+                            let _ = sender.send(CtrlMSG::RawKbdKey(code, *pressed));
+                        }
+                        // Enter and backspace don't produce text events.
+                        let c = match key {
+                            Key::Enter => '\n',
+                            Key::Backspace => '\x08',
+                            _ => continue,
+                        };
+                        if *pressed {
+                            let _ = sender.send(CtrlMSG::RawKbdChar(c as i32));
+                        }
+                    }
+                    Event::Text(text) => {
+                        for c in text.chars() {
+                            let _ = sender.send(CtrlMSG::RawKbdChar(c as i32));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            self.send_modifiers(i.modifiers, sender);
+        });
+    }
+
+    /// egui doesn't have key events for modifiers, so they are generated from modifier state.
+    fn send_modifiers(&mut self, modifiers: Modifiers, sender: &Sender<CtrlMSG>) {
+        let old = self.kbd_modifiers;
+        for (code, was, is) in [
+            (16, old.shift, modifiers.shift),
+            (17, old.ctrl, modifiers.ctrl),
+            (18, old.alt, modifiers.alt),
+        ] {
+            if was != is {
+                let _ = sender.send(CtrlMSG::RawKbdKey(code, is));
+            }
+        }
+        self.kbd_modifiers = modifiers;
+    }
+
+    /// If a gamepad button is waiting for a binding, bind it to the next pressed key.
+    fn update_rebind(&mut self, ui: &Ui, config: &mut Config) {
+        let Some(idx) = self.pad_rebind else {
+            return;
+        };
+        let pressed = ui.input(|i| i.events.iter().find_map(|event| match event {
+            Event::Key { key, pressed: true, .. } => Some(*key),
+            _ => None,
+        }));
+        if let Some(key) = pressed {
+            config.pad_bindings[idx] = key;
+            self.pad_rebind = None;
+        }
+    }
+
+    /// Options menu: scaling, watchpoints, gamepad key bindings
+    fn options_menu(&mut self, ui: &mut Ui, config: &mut Config, sender: &Sender<CtrlMSG>) {
+        ui.label("Scale");
+        ui.radio_value(&mut config.display_scale, DisplayScale::Fit, "Fit to panel");
+        ui.radio_value(&mut config.display_scale, DisplayScale::Integer, "Integer scale");
+        ui.separator();
+        ui.label("Pixel inspector: click shows the address in Memory Explorer, right click toggles a write \
+                  watchpoint.");
+        let text = format!("Clear watchpoints ({})", self.watchpoints.len());
+        if ui.add_enabled(!self.watchpoints.is_empty(), Button::new(text)).clicked() {
+            self.watchpoints.clear();
+            let _ = sender.send(CtrlMSG::ClearWatchpoints);
+            ui.close_menu();
+        }
+        ui.separator();
+        ui.label("Gamepad bindings");
+        Grid::new("pad_bindings").show(ui, |ui| {
+            for (idx, name) in PAD_BUTTON_NAMES.iter().enumerate() {
+                ui.label(*name);
+                let text = match self.pad_rebind == Some(idx) {
+                    true => "Press a key...",
+                    false => config.pad_bindings[idx].name(),
+                };
+                if ui.button(text).clicked() {
+                    self.pad_rebind = Some(idx);
+                }
+                ui.end_row();
+            }
+        });
+        ui.label("Click the display to give it focus.");
     }
 }
 
 impl EmulatorPanel for GraphicsView {
-    fn ui(&mut self, ui: &mut Ui, config: &mut Config, _sender: &Sender<CtrlMSG>) {
+    fn ui(&mut self, ui: &mut Ui, config: &mut Config, sender: &Sender<CtrlMSG>) {
         self.update();
+        self.update_rebind(ui, config);
 
         // Graphics titlebar
         TopBottomPanel::top("graphics_titlebar")
@@ -60,53 +417,137 @@ impl EmulatorPanel for GraphicsView {
                     }
                     if !config.display_visible {
                         return;
-                    }/*
+                    }
                     ui.menu_button("Options", |ui| {
-                        ui.label("No ptions");
-                    });*/
+                        self.options_menu(ui, config, sender);
+                    });
+                    ui.toggle_value(&mut config.display_inspect, "🔍 Inspect")
+                        .on_hover_text("Pixel inspector");
+                    self.capture_controls(ui, config);
                 });
             });
 
+        if !config.display_visible || config.display_inspect {
+            // Hidden or inspected display can't have focus, release any held buttons.
+            self.update_pad(ui, config, sender, false);
+            self.update_rawkbd(ui, sender, false);
+            self.update_mouse(ui, sender, None);
+        }
         if !config.display_visible {
             return;
         }
 
+        self.update_texture(ui);
+
         // Graphics main panel
         TopBottomPanel::top("graphics_main")
             .resizable(true)
             .show_inside(ui, |ui| {
-
-                // Determine image size based on available w / h, whichever fits a smaller image
-                let target_h = clamp(ui.available_height(), 120., 400.); // size limited for performance
-                let target_w = clamp(ui.available_width(), 160., f32::INFINITY);
-                let w;
-                let h;
-                if target_w > target_h * (160. / 120.) {
-                    w = (target_h * (160. / 120.)) as u32;
-                    h = target_h as u32;
-                } else {
-                    w = target_w as u32;
-                    h = (target_w * (120. / 160.)) as u32;
-                }
+                // Scale the image to fit the available space.
+                let (frame_w, frame_h) = self.framebuffer.dimensions();
+                let frame_size = Vec2::new(frame_w as f32, frame_h as f32);
+                let avail = ui.available_size();
+                let fit = (avail.x / frame_size.x).min(avail.y.max(120.) / frame_size.y);
+                let scale = match config.display_scale {
+                    DisplayScale::Fit => fit,
+                    DisplayScale::Integer => fit.floor().max(1.),
+                };
                 ui.with_layout(Layout::top_down(egui::Align::Center), |ui| {
-                    self.displaybuf = Some(image::ImageBuffer::new(w, h));
-                    // This is a terribly inefficient way to make the image
-                    // TODO: figure out how to just rescale the original res pic.
-                    for (x, y, pixels) in self.displaybuf.as_mut().unwrap().enumerate_pixels_mut() {
-                        // px_off = px_x + px_y * 160
-                        let px_off = (x * 160 / w) + (y * 120 / h) * 160;
-                        *pixels = self.framebuffer[px_off as usize];
-                    }
-                    let color_image = egui::ColorImage::from_rgba_unmultiplied(
-                        [w as usize, h as usize],
-                        &self.displaybuf.as_ref().unwrap(),
-                    );
-                    let render_result = RetainedImage::from_color_image("0.png", color_image);
-                    self.image = Some(render_result);
-                    if let Some(img) = &self.image {
-                        img.show(ui);
+                    let Some(texture) = &self.texture else {
+                        return;
+                    };
+                    let response = ui.image((texture.id(), frame_size * scale));
+                    match config.display_inspect {
+                        true => self.inspect(ui, config, sender, &response),
+                        false => self.handle_input(ui, config, sender, &response),
                     }
                 });
             });
     }
-}
\ No newline at end of file
+}
+
+/// Black 160x120 image, shown before the first frame.
+fn blank_frame() -> RgbaImage {
+    RgbaImage::from_pixel(160, 120, Rgba([0, 0, 0, 255]))
+}
+
+/// Raw keyboard key code for a host key. Codes are Windows virtual-key codes.
+fn key_code(key: Key) -> Option<i32> {
+    let code = match key {
+        Key::Backspace => 8,
+        Key::Tab => 9,
+        Key::Enter => 13,
+        Key::Escape => 27,
+        Key::Space => 32,
+        Key::PageUp => 33,
+        Key::PageDown => 34,
+        Key::End => 35,
+        Key::Home => 36,
+        Key::ArrowLeft => 37,
+        Key::ArrowUp => 38,
+        Key::ArrowRight => 39,
+        Key::ArrowDown => 40,
+        Key::Insert => 45,
+        Key::Delete => 46,
+        Key::Num0 => 48,
+        Key::Num1 => 49,
+        Key::Num2 => 50,
+        Key::Num3 => 51,
+        Key::Num4 => 52,
+        Key::Num5 => 53,
+        Key::Num6 => 54,
+        Key::Num7 => 55,
+        Key::Num8 => 56,
+        Key::Num9 => 57,
+        Key::A => 65,
+        Key::B => 66,
+        Key::C => 67,
+        Key::D => 68,
+        Key::E => 69,
+        Key::F => 70,
+        Key::G => 71,
+        Key::H => 72,
+        Key::I => 73,
+        Key::J => 74,
+        Key::K => 75,
+        Key::L => 76,
+        Key::M => 77,
+        Key::N => 78,
+        Key::O => 79,
+        Key::P => 80,
+        Key::Q => 81,
+        Key::R => 82,
+        Key::S => 83,
+        Key::T => 84,
+        Key::U => 85,
+        Key::V => 86,
+        Key::W => 87,
+        Key::X => 88,
+        Key::Y => 89,
+        Key::Z => 90,
+        Key::F1 => 112,
+        Key::F2 => 113,
+        Key::F3 => 114,
+        Key::F4 => 115,
+        Key::F5 => 116,
+        Key::F6 => 117,
+        Key::F7 => 118,
+        Key::F8 => 119,
+        Key::F9 => 120,
+        Key::F10 => 121,
+        Key::F11 => 122,
+        Key::F12 => 123,
+        Key::Semicolon => 186,
+        Key::Plus | Key::Equals => 187,
+        Key::Comma => 188,
+        Key::Minus => 189,
+        Key::Period => 190,
+        Key::Slash => 191,
+        Key::Backtick => 192,
+        Key::OpenBracket => 219,
+        Key::Backslash => 220,
+        Key::CloseBracket => 221,
+        _ => return None,
+    };
+    Some(code)
+}
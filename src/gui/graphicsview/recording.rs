@@ -0,0 +1,124 @@
+//! Recording of display frames into an animated GIF or a PNG sequence.
+//!
+//! Frames are timed by their emulated frame numbers, so recordings play back at the emulated frame
+//! rate no matter how fast the emulator actually ran. When the emulator skips frames, the previous
+//! one stays up longer, and in a PNG sequence the skipped numbers are left out. Frames are encoded
+//! in a background thread, so the GUI doesn't stall.
+//!
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::{self, FilterType};
+use image::{Delay, Frame, RgbaImage};
+
+/// GIF frame delays are in hundredths of a second, and viewers slow down anything shorter than
+/// two. Frames that would be shown for less are merged into the next one.
+const GIF_MIN_DELAY_CS: u32 = 2;
+/// Lower is better quality, but slower.
+const GIF_SPEED: i32 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum RecordFormat {
+    Gif,
+    /// Numbered PNG files in a directory
+    PngSequence,
+}
+
+pub(crate) struct Recording {
+    /// Frames, and their index in the recording counted in emulated frames
+    tx: Sender<(u64, RgbaImage)>,
+    thread: JoinHandle<Result<(), String>>,
+    frames: usize,
+    /// Emulated frame number of the previous frame
+    last_number: Option<i32>,
+    index: u64,
+}
+
+impl Recording {
+    /// Start recording into `path`, which is a file for GIF, and a directory for PNG sequence.
+    pub fn start(format: RecordFormat, path: PathBuf, frame_rate: f32) -> Self {
+        let (tx, rx) = mpsc::channel::<(u64, RgbaImage)>();
+        let thread = thread::spawn(move || match format {
+            RecordFormat::Gif => write_gif(path, frame_rate, rx.iter()),
+            RecordFormat::PngSequence => {
+                for (idx, frame) in rx.iter() {
+                    let file = path.join(format!("frame_{:05}.png", idx));
+                    frame.save(&file).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+        });
+        Self { tx, thread, frames: 0, last_number: None, index: 0 }
+    }
+
+    /// Add a frame to the recording. `number` is the emulated frame number it was captured at. A
+    /// frame with the same number as the previous one replaces it.
+    pub fn push(&mut self, number: i32, frame: &RgbaImage) {
+        let step = match self.last_number {
+            Some(last) => number.wrapping_sub(last),
+            None => 1,
+        };
+        self.last_number = Some(number);
+        // Frame numbers start over when the emulator is restarted.
+        if step != 0 && self.frames > 0 {
+            self.index += step.max(1) as u64;
+        }
+        if self.tx.send((self.index, frame.clone())).is_ok() && step != 0 {
+            self.frames += 1;
+        }
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Stop recording, and wait until everything is written.
+    pub fn stop(self) -> Result<(), String> {
+        drop(self.tx);
+        self.thread.join().map_err(|_| "recording thread panicked".to_string())?
+    }
+}
+
+fn write_gif(path: PathBuf, frame_rate: f32, frames: impl Iterator<Item = (u64, RgbaImage)>) -> Result<(), String> {
+    let file = File::create(&path).map_err(|e| e.to_string())?;
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+    let mut encode = |image: RgbaImage, delay_cs: u32| {
+        let delay = Delay::from_numer_denom_ms(delay_cs * 10, 1);
+        encoder.encode_frame(Frame::from_parts(image, 0, 0, delay)).map_err(|e| e.to_string())
+    };
+
+    // Size of the first frame. GIF can't change size, so later frames are scaled to it.
+    let mut size = None;
+    // Frame waiting for its delay to be known, its index, and the time it started at in hundredths.
+    let mut pending: Option<(u64, RgbaImage)> = None;
+    let mut pending_start = 0;
+    for (idx, frame) in frames {
+        let (w, h) = *size.get_or_insert(frame.dimensions());
+        let frame = match frame.dimensions() == (w, h) {
+            true => frame,
+            false => imageops::resize(&frame, w, h, FilterType::Nearest),
+        };
+        let start = (idx as f64 * 100. / frame_rate as f64).round() as u32;
+        match pending.take() {
+            // Same frame captured again
+            Some((pending_idx, _)) if pending_idx == idx => (),
+            Some((_, image)) if start - pending_start >= GIF_MIN_DELAY_CS => {
+                encode(image, start - pending_start)?;
+                pending_start = start;
+            }
+            _ => (),
+        }
+        pending = Some((idx, frame));
+    }
+    if let Some((_, image)) = pending {
+        let delay = ((100. / frame_rate).round() as u32).max(GIF_MIN_DELAY_CS);
+        encode(image, delay)?;
+    }
+    Ok(())
+}
@@ -20,6 +20,8 @@ use crate::gui::{COL_TEXT, COL_TEXT_HI, FONT_TBL, FONT_TBLH};
 
 
 const MEM_SIZE: usize = 0x2000;
+/// Addresses that can be browsed: RAM, the framebuffer and PSG registers.
+const VIEW_SIZE: usize = 0x6c00;
 const COLOR_SEGMENT_NONE: Color32 = Color32::from_rgb(60, 60, 60);
 const COLOR_SEGMENT_CODE: Color32 = Color32::from_rgb(167, 115, 0);
 const COLOR_SEGMENT_DATA: Color32 = Color32::from_rgb(046, 137, 133);
@@ -99,7 +101,8 @@ impl MemoryView {
 
     /// Range of addresses currently visible on screen
     pub fn get_view_cache_range(&self) -> Range<u32> {
-        (self.view_cache_start as u32)..(self.view_cache_start + self.view_cache_size + 1) as u32
+        let end = (self.view_cache_start + self.view_cache_size + 1).min(VIEW_SIZE);
+        (self.view_cache_start as u32)..end as u32
     }
     /// First address currently visible on screen
     pub fn get_view_cache_start(&self) -> usize {
@@ -145,6 +148,11 @@ impl MemoryView {
         }
     }
 
+    /// Scroll view to an address
+    pub fn jump_to(&mut self, address: usize) {
+        self.view_cache_start = address.saturating_sub(4);
+    }
+
     /// Which segment does an address belong?
     fn get_segment_from_address(&self, address: usize) -> MemorySegment {
         if address >= MEM_SIZE {
@@ -176,7 +184,7 @@ impl MemoryView {
         address: usize,
     ) {
         // Out of bounds
-        if address >= MEM_SIZE {
+        if address >= VIEW_SIZE {
             return;
         }
         let font_color = match self.cpu_pc == address {
@@ -380,7 +388,7 @@ impl EmulatorPanel for MemoryView {
                 // Space accounts for table header row
                 ui.add_space(20.);
                 ui.spacing_mut().slider_width = ui.available_height();
-                ui.add(Slider::new(&mut self.view_cache_start, 0x1fff..=0)
+                ui.add(Slider::new(&mut self.view_cache_start, VIEW_SIZE - 1..=0)
                     .vertical()
                     .smart_aim(false)
                     .show_value(false)
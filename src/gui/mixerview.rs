@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: 2024 sevonj
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! This module contains the Audio Mixer Panel
+//!
+//! Shows the PSG mixer settings the program has made, and lets the user mute and solo channels.
+//! Muting here only changes what plays through the speakers; the program can't see it, and it
+//! doesn't affect WAV recordings.
+//!
+
+use std::sync::mpsc::Sender;
+use egui::{Button, Grid, RichText, TopBottomPanel, Ui};
+use crate::config::Config;
+use crate::emulator::emu_debug::{CtrlMSG, PsgState, PSG_CHANNEL_NAMES};
+use crate::gui::{EmulatorPanel, COL_TEXT, COL_TEXT_HI};
+
+/// MixerView shows the PSG mixer.
+pub(crate) struct MixerView {
+    state: PsgState,
+}
+
+impl MixerView {
+    pub fn new() -> Self {
+        MixerView {
+            state: PsgState {
+                pan: vec![0; PSG_CHANNEL_NAMES.len()],
+                master: 255,
+                mute: 0,
+                solo: 0,
+                channels: Vec::new(),
+            },
+        }
+    }
+
+    /// Emulator sends this when PSG state changes.
+    pub fn set_state(&mut self, state: PsgState) {
+        self.state = state;
+    }
+}
+
+/// Pan as text: L, R, or C for center.
+fn pan_text(pan: i32) -> String {
+    match pan {
+        0 => "C".into(),
+        ..=-1 => format!("L{}", -pan),
+        _ => format!("R{}", pan),
+    }
+}
+
+impl EmulatorPanel for MixerView {
+    fn ui(&mut self, ui: &mut Ui, config: &mut Config, sender: &Sender<CtrlMSG>) {
+        // Mixer titlebar
+        TopBottomPanel::top("mixer_titlebar")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let toggle_text = if config.mixer_visible { "⏷ Mixer" } else { "⏵ Mixer" };
+                    if ui.add(Button::new(toggle_text).frame(false)).clicked() {
+                        config.mixer_visible = !config.mixer_visible;
+                    }
+                    if !config.mixer_visible {
+                        return;
+                    }
+                    ui.label(format!("Master: {}%", self.state.master * 100 / 255));
+                    if ui.button("Unmute all").clicked() {
+                        config.mixer_mute = 0;
+                        config.mixer_solo = 0;
+                        let _ = sender.send(CtrlMSG::SetPsgMixer(0, 0));
+                    }
+                });
+            });
+
+        if !config.mixer_visible {
+            return;
+        }
+
+        TopBottomPanel::top("mixer_main")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                let mut changed = false;
+                Grid::new("mixer_grid").striped(true).show(ui, |ui| {
+                    ui.label("Channel");
+                    ui.label("Pan");
+                    ui.label("Program");
+                    ui.label("");
+                    ui.end_row();
+                    for (ch, name) in PSG_CHANNEL_NAMES.iter().enumerate() {
+                        let bit = 1 << ch;
+                        ui.label(*name);
+                        ui.label(pan_text(self.state.pan.get(ch).copied().unwrap_or(0)));
+
+                        // What the program has set
+                        let program = match (self.state.mute & bit != 0, self.state.solo & bit != 0) {
+                            (true, _) => "Muted",
+                            (false, true) => "Solo",
+                            (false, false) => "",
+                        };
+                        ui.label(RichText::new(program).color(COL_TEXT));
+
+                        ui.horizontal(|ui| {
+                            let muted = config.mixer_mute & bit != 0;
+                            let mute_text = RichText::new("M").color(if muted { COL_TEXT_HI } else { COL_TEXT });
+                            if ui.selectable_label(muted, mute_text).on_hover_text("Mute").clicked() {
+                                config.mixer_mute ^= bit;
+                                changed = true;
+                            }
+                            let solo = config.mixer_solo & bit != 0;
+                            let solo_text = RichText::new("S").color(if solo { COL_TEXT_HI } else { COL_TEXT });
+                            if ui.selectable_label(solo, solo_text).on_hover_text("Solo").clicked() {
+                                config.mixer_solo ^= bit;
+                                changed = true;
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+                if changed {
+                    let _ = sender.send(CtrlMSG::SetPsgMixer(config.mixer_mute, config.mixer_solo));
+                }
+            });
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2024 sevonj
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! This module contains the Printer Panel
+//!
+
+use std::sync::mpsc::Sender;
+use egui::{Button, Color32, Frame, Margin, RichText, ScrollArea, TopBottomPanel, Ui};
+use crate::config::Config;
+use crate::emulator::emu_debug::CtrlMSG;
+use crate::gui::EmulatorPanel;
+
+const COLOR_PAPER: Color32 = Color32::from_rgb(0xf4, 0xf1, 0xe6);
+const COLOR_INK: Color32 = Color32::from_rgb(0x20, 0x20, 0x20);
+/// Form feed ends a page.
+const PAGE_BREAK: char = '\x0c';
+
+/// PrinterView shows everything the printer has printed, page by page.
+pub(crate) struct PrinterView {
+    printout: String,
+}
+
+impl PrinterView {
+    pub fn new() -> Self {
+        PrinterView {
+            printout: String::new(),
+        }
+    }
+
+    /// Add printed text. Emulator sends this as it gets printed.
+    pub fn print(&mut self, text: &str, config: &mut Config) {
+        self.printout += text;
+        // Pop the panel open when something gets printed.
+        config.printer_visible = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.printout.clear();
+    }
+}
+
+impl EmulatorPanel for PrinterView {
+    fn ui(&mut self, ui: &mut Ui, config: &mut Config, _sender: &Sender<CtrlMSG>) {
+        // Printer titlebar
+        TopBottomPanel::top("printer_titlebar")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let toggle_text = if config.printer_visible { "⏷ Printer" } else { "⏵ Printer" };
+                    if ui.add(Button::new(toggle_text).frame(false)).clicked() {
+                        config.printer_visible = !config.printer_visible;
+                    }
+                    if !config.printer_visible {
+                        return;
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.clear();
+                    }
+                });
+            });
+
+        if !config.printer_visible {
+            return;
+        }
+
+        TopBottomPanel::top("printer_main")
+            .resizable(true)
+            .show_inside(ui, |ui| {
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for page in self.printout.split(PAGE_BREAK) {
+                            Frame::none()
+                                .fill(COLOR_PAPER)
+                                .inner_margin(Margin::same(8.0))
+                                .show(ui, |ui| {
+                                    ui.label(RichText::new(page).monospace().color(COLOR_INK));
+                                    ui.allocate_space(egui::vec2(ui.available_width(), 0.0))
+                                });
+                            ui.add_space(4.0);
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,132 @@
+// SPDX-FileCopyrightText: 2024 sevonj
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! This module contains the PSG Channels Panel
+//!
+//! Shows an oscilloscope and the current settings of each PSG channel. The waveforms are the
+//! channels' own output in emulated time, before mixing.
+//!
+
+use std::sync::mpsc::Sender;
+use egui::{pos2, vec2, Button, Color32, Grid, Rect, RichText, Sense, Shape, Stroke, TopBottomPanel, Ui};
+use crate::config::Config;
+use crate::emulator::emu_debug::{CtrlMSG, PsgChannelState, PSG_CHANNEL_NAMES, PSG_STATUS_ACTIVE};
+use crate::gui::{EmulatorPanel, COL_TEXT, COL_TEXT_HI};
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const SCOPE_SIZE: egui::Vec2 = vec2(192., 32.);
+const COLOR_SCOPE_BG: Color32 = Color32::from_rgb(20, 20, 20);
+const COLOR_SCOPE_LINE: Color32 = Color32::from_rgb(80, 220, 120);
+const COLOR_ACTIVE: Color32 = Color32::from_rgb(80, 220, 120);
+
+/// PsgView shows what each PSG channel is doing.
+pub(crate) struct PsgView {
+    channels: Vec<PsgChannelState>,
+}
+
+impl PsgView {
+    pub fn new() -> Self {
+        PsgView { channels: Vec::new() }
+    }
+
+    /// Emulator sends this while the PSG is running.
+    pub fn set_channels(&mut self, channels: Vec<PsgChannelState>) {
+        self.channels = channels;
+    }
+}
+
+/// Note name of a MIDI key, and the frequency it plays at. Keys outside 0-127 after wrapping
+/// don't have a name.
+fn note_text(key: i32, freq: f32) -> String {
+    match key % 128 {
+        key @ 0.. => format!("{}{} {:.1} Hz", NOTE_NAMES[key as usize % 12], key / 12 - 1, freq),
+        _ => format!("- {:.1} Hz", freq),
+    }
+}
+
+/// Draw the waveform. Starts at a rising zero crossing when there is one, so periodic waves stand
+/// still.
+fn scope(ui: &mut Ui, waveform: &[f32]) {
+    let (response, painter) = ui.allocate_painter(SCOPE_SIZE, Sense::hover());
+    let rect: Rect = response.rect;
+    painter.rect_filled(rect, 2., COLOR_SCOPE_BG);
+    let shown = waveform.len() / 2;
+    if shown < 2 {
+        return;
+    }
+    let start = (0..waveform.len() - shown)
+        .find(|&i| waveform[i] <= 0. && waveform[i + 1] > 0.)
+        .unwrap_or(waveform.len() - shown);
+    let points = waveform[start..start + shown]
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| {
+            let x = rect.left() + rect.width() * i as f32 / (shown - 1) as f32;
+            let y = rect.center().y - sample.clamp(-1., 1.) * rect.height() / 2.;
+            pos2(x, y)
+        })
+        .collect();
+    painter.add(Shape::line(points, Stroke::new(1., COLOR_SCOPE_LINE)));
+}
+
+impl EmulatorPanel for PsgView {
+    fn ui(&mut self, ui: &mut Ui, config: &mut Config, _sender: &Sender<CtrlMSG>) {
+        // PSG titlebar
+        TopBottomPanel::top("psg_titlebar")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let toggle_text = if config.psgview_visible { "⏷ PSG Channels" } else { "⏵ PSG Channels" };
+                    if ui.add(Button::new(toggle_text).frame(false)).clicked() {
+                        config.psgview_visible = !config.psgview_visible;
+                    }
+                });
+            });
+
+        if !config.psgview_visible {
+            return;
+        }
+
+        TopBottomPanel::top("psg_main")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                Grid::new("psg_grid").striped(true).show(ui, |ui| {
+                    for (ch, state) in self.channels.iter().enumerate() {
+                        let active = state.status & PSG_STATUS_ACTIVE != 0;
+                        let name = RichText::new(PSG_CHANNEL_NAMES.get(ch).copied().unwrap_or("?"));
+                        ui.label(if active { name.color(COLOR_ACTIVE) } else { name.color(COL_TEXT) });
+                        scope(ui, &state.waveform);
+                        ui.vertical(|ui| {
+                            ui.label(RichText::new(note_text(state.note, state.freq)).color(COL_TEXT_HI));
+                            // The envelope replaces the register, and drives the width between
+                            // 50% and 100%.
+                            let percent = match state.env_flags.contains(&"PW") {
+                                true => 50 + state.env_position * 50 / 255,
+                                false => (state.pw & 0xff) * 100 / 255,
+                            };
+                            let pw = match ch {
+                                0 | 1 => format!("pw {}%", percent),
+                                2 => format!("duty {}%", percent),
+                                4 => format!("rate {}", state.pw),
+                                _ => String::new(),
+                            };
+                            ui.label(format!("vol {}  {}", state.vol & 0xff, pw));
+                        });
+                        ui.vertical(|ui| {
+                            let flags = match state.env_flags.is_empty() {
+                                true => "-".to_string(),
+                                false => state.env_flags.join(" "),
+                            };
+                            ui.label(format!("env {}", flags));
+                            match state.env_stage {
+                                Some(stage) => ui.label(format!("{} {}", stage, state.env_position)),
+                                None => ui.label(format!("pos {}", state.env_position)),
+                            };
+                        });
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
@@ -0,0 +1,92 @@
+// SPDX-FileCopyrightText: 2024 sevonj
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! This module contains the Text Display Panel
+//!
+
+use std::sync::mpsc::Sender;
+use egui::{Button, ColorImage, Layout, TextureHandle, TextureOptions, TopBottomPanel, Ui, Vec2};
+use crate::config::Config;
+use crate::emulator::emu_debug::{CtrlMSG, TextScreen};
+use crate::gui::EmulatorPanel;
+
+/// Screen is always shown at this aspect ratio, so 40 column text is twice as wide.
+const ASPECT: f32 = 640. / 400.;
+
+/// TextView shows the text mode display.
+pub(crate) struct TextView {
+    /// Latest screen that hasn't been uploaded into the texture yet.
+    pending: Option<ColorImage>,
+    texture: Option<TextureHandle>,
+}
+
+impl TextView {
+    pub fn new() -> Self {
+        TextView {
+            pending: None,
+            texture: None,
+        }
+    }
+
+    /// Show a new screen. Emulator sends this whenever the text changes.
+    pub fn set_screen(&mut self, screen: TextScreen, config: &mut Config) {
+        let pixels: Vec<u8> = screen.pixels.iter().flat_map(|px| px.0).collect();
+        self.pending = Some(ColorImage::from_rgba_unmultiplied([screen.width, screen.height], &pixels));
+        // Pop the panel open when the program uses it.
+        if self.texture.is_none() {
+            config.textview_visible = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = None;
+        self.texture = None;
+    }
+}
+
+impl EmulatorPanel for TextView {
+    fn ui(&mut self, ui: &mut Ui, config: &mut Config, _sender: &Sender<CtrlMSG>) {
+        if let Some(image) = self.pending.take() {
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::NEAREST),
+                None => self.texture = Some(ui.ctx().load_texture("text_screen", image, TextureOptions::NEAREST)),
+            }
+        }
+
+        // Text titlebar
+        TopBottomPanel::top("text_titlebar")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let toggle_text = if config.textview_visible { "⏷ Text" } else { "⏵ Text" };
+                    if ui.add(Button::new(toggle_text).frame(false)).clicked() {
+                        config.textview_visible = !config.textview_visible;
+                    }
+                });
+            });
+
+        if !config.textview_visible {
+            return;
+        }
+
+        TopBottomPanel::top("text_main")
+            .resizable(true)
+            .show_inside(ui, |ui| {
+                let avail = ui.available_size();
+                let h = avail.y.max(100.).min(avail.x / ASPECT);
+                let size = Vec2::new(h * ASPECT, h);
+                ui.with_layout(Layout::top_down(egui::Align::Center), |ui| {
+                    match &self.texture {
+                        Some(texture) => {
+                            ui.image((texture.id(), size));
+                        }
+                        None => {
+                            let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                            ui.painter().rect_filled(rect, 0.0, egui::Color32::BLACK);
+                        }
+                    }
+                });
+            });
+    }
+}
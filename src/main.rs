@@ -21,7 +21,9 @@
 extern crate num_derive;
 
 use crate::gui::memoryview::MemoryView;
-use std::{sync::mpsc, thread};
+use std::{fs, sync::mpsc, thread};
+use std::path::PathBuf;
+use std::str::FromStr;
 use egui::{Context, Vec2, ViewportBuilder};
 use egui_extras::install_image_loaders;
 
@@ -31,6 +33,7 @@ pub mod emulator;
 pub mod gui;
 
 use editor::Editor;
+use libttktk::b91::B91;
 
 use emulator::emu_debug::{CtrlMSG, ReplyMSG};
 use gui::gui_editor::file_actions::FileStatus;
@@ -39,6 +42,10 @@ use crate::config::Config;
 use crate::gui::cpuview::CPUView;
 use crate::gui::graphicsview::GraphicsView;
 use crate::gui::legacytermview::LegacyTermView;
+use crate::gui::mixerview::MixerView;
+use crate::gui::psgview::PsgView;
+use crate::gui::printerview::PrinterView;
+use crate::gui::textview::TextView;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
@@ -66,6 +73,10 @@ pub struct TitoApp {
     #[serde(skip)] memoryview: MemoryView,
     #[serde(skip)] cpuview: CPUView,
     #[serde(skip)] legacytermview: LegacyTermView,
+    #[serde(skip)] printerview: PrinterView,
+    #[serde(skip)] mixerview: MixerView,
+    #[serde(skip)] psgview: PsgView,
+    #[serde(skip)] textview: TextView,
 
     // GUI settings
     #[serde(skip)] guimode: GuiMode,
@@ -116,6 +127,10 @@ impl Default for TitoApp {
             memoryview: MemoryView::new(),
             cpuview: CPUView::new(),
             legacytermview: LegacyTermView::new(rx_devcrt, tx_devkbd, rx_devkbdreq),
+            printerview: PrinterView::new(),
+            mixerview: MixerView::new(),
+            psgview: PsgView::new(),
+            textview: TextView::new(),
 
             guimode: GuiMode::Editor,
         }
@@ -174,11 +189,18 @@ impl TitoApp {
                     ReplyMSG::Mem(vec) => {
                         self.memoryview.set_view_cache(self.memoryview.get_view_cache_start(), vec)
                     }
+                    ReplyMSG::Word(addr, value) => self.graphicsview.set_inspected_value(addr, value),
                     ReplyMSG::SegmentOffsets(start_code, start_data, start_stack) => {
                         self.memoryview.start_code = start_code;
                         self.memoryview.start_data = start_data;
                         self.memoryview.start_stack = start_stack;
                     }
+                    ReplyMSG::PrinterOutput(text) => self.printerview.print(&text, &mut self.config),
+                    ReplyMSG::TextScreen(screen) => self.textview.set_screen(screen, &mut self.config),
+                    ReplyMSG::PsgState(mut state) => {
+                        self.psgview.set_channels(std::mem::take(&mut state.channels));
+                        self.mixerview.set_state(state);
+                    }
                 }
             } else {
                 break;
@@ -195,6 +217,21 @@ impl TitoApp {
         let _ = self.tx_ctrl.send(CtrlMSG::SetRate(speed));
     }
 
+    /// Device settings only need to be sent when they change, or before the machine is turned on.
+    fn send_device_settings(&mut self) {
+        let _ = self.tx_ctrl.send(CtrlMSG::SetStdinPath(self.config.dev_stdin_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetStdoutPath(self.config.dev_stdout_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetMidiRecordPath(self.config.dev_midi_record_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetPsgRecordPath(self.config.dev_psg_record_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetPsgMixer(self.config.mixer_mute, self.config.mixer_solo));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetDiskImagePath(self.config.dev_disk_image_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetPrinterPath(self.config.dev_printer_path.clone()));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetUartBridge(self.config.dev_uart_mode, self.config.dev_uart_addr.clone()));
+        let rng_seed = self.config.dev_rng_deterministic.then_some(self.config.dev_rng_seed);
+        let _ = self.tx_ctrl.send(CtrlMSG::SetRngSeed(rng_seed));
+        let _ = self.tx_ctrl.send(CtrlMSG::SetRefreshRate(self.config.dev_display_refresh_rate));
+    }
+
     fn stop_emulation(&mut self) {
         self.emu_running = false;
         self.legacytermview.unjam_input_wait();
@@ -219,7 +256,37 @@ impl eframe::App for TitoApp {
     }
 }
 
+/// Headless mode: run a compiled program without the GUI, and record what the PSG plays.
+/// Arguments: PROGRAM.b91 OUT.wav [SECONDS (10)] [CLOCK_HZ (1000000)]
+fn render_wav(args: &[String]) -> Result<(), String> {
+    let usage = "usage: titomachine --render-wav PROGRAM.b91 OUT.wav [SECONDS] [CLOCK_HZ]";
+    let [program, out, rest @ ..] = args else {
+        return Err(usage.into());
+    };
+    let seconds = match rest.first() {
+        Some(arg) => arg.parse().map_err(|_| usage.to_string())?,
+        None => 10.,
+    };
+    let rate = match rest.get(1) {
+        Some(arg) => arg.parse().map_err(|_| usage.to_string())?,
+        None => 1000000.,
+    };
+    let source = fs::read_to_string(program).map_err(|e| format!("{}: {}", program, e))?;
+    let b91 = B91::from_str(&source).map_err(|e| format!("{}: {}", program, e))?;
+    emulator::render_wav(b91, PathBuf::from(out), seconds, rate);
+    Ok(())
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--render-wav") {
+        if let Err(e) = render_wav(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: ViewportBuilder::default()
             .with_app_id("fi.sevonj.titomachine")